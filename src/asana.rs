@@ -7,15 +7,39 @@ pub struct Exported {
     pub data: Vec<Task>,
 }
 
-/// Asana task data, according to https://developers.asana.com/docs/task
+/// Single page of paginated Asana API response.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Page {
+    pub data: Vec<Task>,
+    #[serde(default)]
+    pub next_page: Option<NextPage>,
+}
+
+/// Reference to the next page of paginated Asana API response.
 #[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NextPage {
+    pub offset: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+}
+
+/// Asana task data, according to https://developers.asana.com/docs/task
+///
+/// Only `gid`, `name` and `created_at` are required, other fields may be missing if export was
+/// made with trimmed `opt_fields` list.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Task {
     pub gid: String,
+    #[serde(default)]
     pub resource_type: String,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub approval_status: Option<String>,
+    #[serde(default)]
     pub assignee_status: String,
+    #[serde(default)]
     pub completed: bool,
     pub completed_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -33,15 +57,23 @@ pub struct Task {
     pub html_notes: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_rendered_as_separator: Option<bool>,
+    #[serde(default)]
     pub liked: bool,
+    #[serde(default)]
     pub likes: Vec<Like>,
+    #[serde(default)]
     pub projects: Vec<ProjectReference>,
+    #[serde(default)]
     pub memberships: Vec<TaskMembership>,
+    #[serde(default)]
     pub modified_at: DateTime<Utc>,
+    #[serde(default)]
     pub notes: String,
+    #[serde(default)]
     pub num_likes: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub num_subtasks: Option<u64>,
+    #[serde(default)]
     pub resource_subtype: String,
     pub start_on: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -50,14 +82,17 @@ pub struct Task {
     pub assignee_section: Option<SectionReference>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub custom_fields: Option<Vec<CustomField>>,
+    #[serde(default)]
     pub followers: Vec<UserReference>,
     pub parent: Option<TaskReference>,
+    #[serde(default)]
     pub tags: Vec<TagReference>,
+    #[serde(default)]
     pub workspace: WorkspaceReference,
     pub subtasks: Option<Vec<Task>>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct TaskMembership {
     pub project: ProjectReference,
     pub section: SectionReference,
@@ -69,9 +104,10 @@ pub struct TaskExternal {
     pub data: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct TaskReference {
     pub gid: String,
+    #[serde(default)]
     pub resource_type: String,
 }
 
@@ -81,18 +117,25 @@ pub struct Like {
     pub user: UserReference,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct Reference {
     pub gid: String,
+    #[serde(default)]
     pub resource_type: String,
+    #[serde(default)]
     pub name: String,
 }
 
+/// Custom field value of task, according to https://developers.asana.com/reference/custom-fields
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CustomField {
     pub gid: String,
+    #[serde(default)]
     pub resource_type: String,
-    pub created_by: UserReference,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asana_created_field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<UserReference>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub currency_code: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -100,37 +143,59 @@ pub struct CustomField {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub custom_label_position: Option<CustomLabelPosition>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_value: Option<CustomFieldDateValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
-    pub display_value: String,
+    pub display_value: Option<String>,
+    #[serde(default)]
     pub enabled: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enum_options: Option<Vec<EnumOption>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub enum_value: Option<EnumOption>,
+    #[serde(default)]
     pub format: CustomFieldFormat,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub has_notifications_enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_formula_field: Option<bool>,
+    #[serde(default)]
     pub is_global_to_workspace: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_value_read_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub multi_enum_values: Option<Vec<EnumOption>>,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub number_value: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub people_value: Option<Vec<UserReference>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub precision: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privacy_setting: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub representation_type: Option<CustomFieldRepresentationType>,
+    #[serde(default)]
     pub resource_subtype: String,
-    pub text_value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text_value: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum CustomLabelPosition {
     #[serde(rename = "prefix")]
     Prefix,
-    #[serde(rename = "prefix")]
+    #[serde(rename = "suffix")]
     Suffix,
+    /// Position added to Asana after this converter was written.
+    #[serde(rename = "unknown", other)]
+    Unknown,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
 pub enum CustomFieldFormat {
     #[serde(rename = "currency")]
     Currency,
@@ -140,15 +205,172 @@ pub enum CustomFieldFormat {
     Percentage,
     #[serde(rename = "custom")]
     Custom,
+    #[serde(rename = "duration")]
+    Duration,
     #[serde(rename = "none")]
+    #[default]
     None,
+    /// Format added to Asana after this converter was written.
+    #[serde(rename = "unknown", other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CustomFieldRepresentationType {
+    #[serde(rename = "text")]
+    Text,
+    #[serde(rename = "enum")]
+    Enum,
+    #[serde(rename = "multi_enum")]
+    MultiEnum,
+    #[serde(rename = "number")]
+    Number,
+    #[serde(rename = "date")]
+    Date,
+    #[serde(rename = "people")]
+    People,
+    #[serde(rename = "formula")]
+    Formula,
+    #[serde(rename = "custom_id")]
+    CustomId,
+    /// Representation type added to Asana after this converter was written.
+    #[serde(rename = "unknown", other)]
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CustomFieldDateValue {
+    pub date: Option<NaiveDate>,
+    pub date_time: Option<DateTime<Utc>>,
+}
+
+/// Story of task (comment or system event), according to https://developers.asana.com/reference/stories
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Story {
+    pub gid: String,
+    #[serde(default)]
+    pub resource_type: String,
+    pub created_at: DateTime<Utc>,
+    pub created_by: Option<UserReference>,
+    #[serde(default)]
+    pub resource_subtype: String,
+    #[serde(default)]
+    pub text: String,
+    /// Deprecated `comment` or `system` story type, still returned by API.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub story_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<TaskReference>,
+}
+
+impl Story {
+    /// Check if story is comment added by user, not system event.
+    pub fn is_comment(&self) -> bool {
+        self.resource_subtype == "comment_added" || self.story_type.as_deref() == Some("comment")
+    }
+}
+
+/// Project, according to https://developers.asana.com/reference/projects
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Project {
+    pub gid: String,
+    #[serde(default)]
+    pub resource_type: String,
+    pub name: String,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_on: Option<NaiveDate>,
+    /// Deprecated synonym of `due_on`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_date: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_on: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<UserReference>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team: Option<TeamReference>,
+}
+
+/// Team, according to https://developers.asana.com/reference/teams
+///
+/// `projects` are not returned by teams endpoint, they may be added from team projects endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Team {
+    pub gid: String,
+    #[serde(default)]
+    pub resource_type: String,
+    pub name: String,
+    #[serde(default)]
+    pub projects: Vec<ProjectReference>,
+}
+
+/// Portfolio, according to https://developers.asana.com/reference/portfolios
+///
+/// `items` are not returned by portfolios endpoint, they may be added from portfolio items
+/// endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Portfolio {
+    pub gid: String,
+    #[serde(default)]
+    pub resource_type: String,
+    pub name: String,
+    #[serde(default)]
+    pub items: Vec<Reference>,
+}
+
+/// Section of project, according to https://developers.asana.com/reference/sections
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Section {
+    pub gid: String,
+    #[serde(default)]
+    pub resource_type: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<ProjectReference>,
+}
+
+/// Attachment of task, according to https://developers.asana.com/reference/attachments
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Attachment {
+    pub gid: String,
+    #[serde(default)]
+    pub resource_type: String,
+    pub name: String,
+    #[serde(default)]
+    pub resource_subtype: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<TaskReference>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permanent_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub view_url: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EnumOption {
     pub gid: String,
+    #[serde(default)]
     pub resource_type: String,
-    pub color: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default)]
     pub enabled: bool,
     pub name: String,
 }
@@ -157,4 +379,5 @@ pub type UserReference = Reference;
 pub type ProjectReference = Reference;
 pub type SectionReference = Reference;
 pub type TagReference = Reference;
+pub type TeamReference = Reference;
 pub type WorkspaceReference = Reference;
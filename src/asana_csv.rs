@@ -0,0 +1,217 @@
+//! Asana web UI CSV export reader
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+};
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    asana::{self, Reference},
+    hierarchy::nest_subtasks_by_index,
+};
+
+/// Row of Asana CSV export, with standard columns.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Row {
+    #[serde(rename = "Task ID")]
+    pub task_id: String,
+    #[serde(rename = "Created At")]
+    pub created_at: String,
+    #[serde(rename = "Completed At")]
+    pub completed_at: String,
+    #[serde(rename = "Last Modified")]
+    pub last_modified: String,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Section/Column")]
+    pub section: String,
+    #[serde(rename = "Assignee")]
+    pub assignee: String,
+    #[serde(rename = "Start Date")]
+    pub start_date: String,
+    #[serde(rename = "Due Date")]
+    pub due_date: String,
+    #[serde(rename = "Tags")]
+    pub tags: String,
+    #[serde(rename = "Notes")]
+    pub notes: String,
+    #[serde(rename = "Projects")]
+    pub projects: String,
+    #[serde(rename = "Parent task")]
+    pub parent_task: String,
+    #[serde(rename = "Blocked By", alias = "Blocked By (Dependencies)")]
+    pub blocked_by: String,
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .or_else(|| {
+            DateTime::parse_from_rfc3339(value)
+                .ok()
+                .map(|date| date.date_naive())
+        })
+}
+
+fn parse_datetime(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|| parse_date(value).map(|date| date.and_time(NaiveTime::MIN).and_utc()))
+}
+
+/// Split comma-separated list, skipping empty items.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reference to resource that CSV export gives by name only. Its synthetic gid is made of type
+/// and name, so that it is the same in every conversion.
+fn reference(resource_type: &str, name: &str) -> Reference {
+    Reference {
+        gid: format!("{}:{}", resource_type, name),
+        resource_type: resource_type.to_string(),
+        name: name.to_string(),
+    }
+}
+
+/// Reference to section of project, sections of different projects may have the same name.
+fn section_reference(project: &Reference, name: &str) -> Reference {
+    Reference {
+        gid: format!("section:{}/{}", project.name, name),
+        ..reference("section", name)
+    }
+}
+
+/// Error of CSV row with given task ID.
+fn row_error(task_id: &str, message: String) -> csv::Error {
+    csv::Error::from(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("task {}: {}", task_id, message),
+    ))
+}
+
+impl Row {
+    /// Convert CSV row to Asana task without subtasks.
+    ///
+    /// Creation time is required: it is the `entry` of converted task, which must not change
+    /// between conversions.
+    pub fn into_task(self) -> Result<asana::Task, csv::Error> {
+        let created_at = parse_datetime(&self.created_at).ok_or_else(|| {
+            row_error(
+                &self.task_id,
+                format!("invalid Created At \"{}\"", self.created_at),
+            )
+        })?;
+        let completed_at = parse_datetime(&self.completed_at);
+        let projects: Vec<Reference> = split_list(&self.projects)
+            .iter()
+            .map(|project| reference("project", project))
+            .collect();
+        let memberships = match (projects.first(), self.section.trim()) {
+            (Some(project), section) if !section.is_empty() => vec![asana::TaskMembership {
+                project: project.clone(),
+                section: section_reference(project, section),
+            }],
+            _ => Vec::new(),
+        };
+        let dependencies: Vec<asana::TaskReference> = split_list(&self.blocked_by)
+            .into_iter()
+            .map(|gid| asana::TaskReference {
+                gid,
+                resource_type: "task".to_string(),
+            })
+            .collect();
+
+        Ok(asana::Task {
+            gid: self.task_id,
+            resource_type: "task".to_string(),
+            name: self.name,
+            completed: completed_at.is_some(),
+            completed_at,
+            created_at,
+            dependencies: if dependencies.is_empty() {
+                None
+            } else {
+                Some(dependencies)
+            },
+            due_on: parse_date(&self.due_date),
+            projects,
+            memberships,
+            modified_at: parse_datetime(&self.last_modified).unwrap_or(created_at),
+            notes: self.notes,
+            resource_subtype: "default_task".to_string(),
+            start_on: parse_datetime(&self.start_date),
+            assignee: if self.assignee.trim().is_empty() {
+                None
+            } else {
+                Some(reference("user", self.assignee.trim()))
+            },
+            tags: split_list(&self.tags)
+                .iter()
+                .map(|tag| reference("tag", tag))
+                .collect(),
+            ..asana::Task::default()
+        })
+    }
+}
+
+/// Read Asana tasks from CSV export, rebuilding subtask tree from `Parent task` column.
+///
+/// `Parent task` column may contain either ID or name of parent task.
+pub fn read_tasks<R: Read>(reader: R) -> Result<Vec<asana::Task>, csv::Error> {
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let mut rows = Vec::new();
+    for row in csv_reader.deserialize() {
+        let row: Row = row?;
+        rows.push(row);
+    }
+
+    let mut indices_by_id: HashMap<&str, usize> = HashMap::new();
+    let mut indices_by_name: HashMap<&str, usize> = HashMap::new();
+    for (index, row) in rows.iter().enumerate() {
+        indices_by_id.insert(&row.task_id, index);
+        indices_by_name.entry(&row.name).or_insert(index);
+    }
+    let parents: Vec<Option<usize>> = rows
+        .iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let parent_task = row.parent_task.trim();
+            if parent_task.is_empty() {
+                return None;
+            }
+            indices_by_id
+                .get(parent_task)
+                .or_else(|| indices_by_name.get(parent_task))
+                .copied()
+                .filter(|&parent_index| parent_index != index)
+        })
+        .collect();
+
+    let mut tasks = rows
+        .into_iter()
+        .map(Row::into_task)
+        .collect::<Result<Vec<asana::Task>, csv::Error>>()?;
+    for (index, parent) in parents.iter().enumerate() {
+        if let Some(parent_index) = parent {
+            let parent_gid = tasks[*parent_index].gid.clone();
+            tasks[index].parent = Some(asana::TaskReference {
+                gid: parent_gid,
+                resource_type: "task".to_string(),
+            });
+        }
+    }
+
+    Ok(nest_subtasks_by_index(tasks, &parents))
+}
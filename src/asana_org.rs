@@ -0,0 +1,250 @@
+//! Asana organization export reader
+//!
+//! Organization export consists of separate collections of users, teams, projects, sections, tags
+//! and tasks, linked by gid. References may be either gid strings or objects with `gid` field.
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::{asana, hierarchy::nest_subtasks};
+
+/// Collections of organization export, each one is a list of JSON objects.
+#[derive(Clone, Debug, Default)]
+pub struct OrganizationExport {
+    pub collections: HashMap<String, Vec<Value>>,
+}
+
+/// Fields of task that refer to single resource.
+const REFERENCE_FIELDS: [&str; 5] = [
+    "assignee",
+    "assignee_section",
+    "completed_by",
+    "parent",
+    "workspace",
+];
+
+/// Fields of task that are lists of references.
+const REFERENCE_LIST_FIELDS: [&str; 5] = [
+    "dependencies",
+    "dependents",
+    "followers",
+    "projects",
+    "tags",
+];
+
+/// Resource type of collection items, for items that don't have `resource_type` field.
+fn get_collection_resource_type(collection: &str) -> &str {
+    match collection {
+        "users" => "user",
+        "teams" => "team",
+        "projects" => "project",
+        "sections" => "section",
+        "tags" => "tag",
+        "workspaces" | "organizations" => "workspace",
+        _ => "task",
+    }
+}
+
+/// Get gid of reference that is either gid string or object with `gid` field.
+fn get_reference_gid(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(gid) => Some(gid),
+        Value::Object(object) => object.get("gid").and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+impl OrganizationExport {
+    /// Add items of collection from JSON value: array, API response page or single item.
+    pub fn add_items(&mut self, collection: &str, value: Value) {
+        let items = self.collections.entry(collection.to_string()).or_default();
+        match value {
+            Value::Array(mut values) => items.append(&mut values),
+            Value::Object(mut object) if object.contains_key("data") => {
+                if let Some(Value::Array(mut values)) = object.remove("data") {
+                    items.append(&mut values);
+                }
+            }
+            value @ Value::Object(_) => items.push(value),
+            _ => {}
+        }
+    }
+
+    /// Get names and resource types of all resources by gid.
+    fn get_resources(&self) -> HashMap<String, (String, String)> {
+        let mut resources = HashMap::new();
+        for (collection, items) in &self.collections {
+            for item in items {
+                if let (Some(gid), Some(name)) = (
+                    item.get("gid").and_then(Value::as_str),
+                    item.get("name").and_then(Value::as_str),
+                ) {
+                    let resource_type = item
+                        .get("resource_type")
+                        .and_then(Value::as_str)
+                        .unwrap_or_else(|| get_collection_resource_type(collection));
+                    resources.insert(
+                        gid.to_string(),
+                        (resource_type.to_string(), name.to_string()),
+                    );
+                }
+            }
+        }
+        resources
+    }
+
+    /// Get project of each section, and section of each task for sections that list their tasks.
+    fn get_section_links(&self) -> (HashMap<String, String>, HashMap<String, Vec<String>>) {
+        let mut section_projects = HashMap::new();
+        let mut task_sections: HashMap<String, Vec<String>> = HashMap::new();
+        for section in self.collections.get("sections").into_iter().flatten() {
+            let section_gid = match section.get("gid").and_then(Value::as_str) {
+                Some(gid) => gid,
+                None => continue,
+            };
+            if let Some(project_gid) = section.get("project").and_then(get_reference_gid) {
+                section_projects.insert(section_gid.to_string(), project_gid.to_string());
+            }
+            if let Some(Value::Array(tasks)) = section.get("tasks") {
+                for task_gid in tasks.iter().filter_map(get_reference_gid) {
+                    task_sections
+                        .entry(task_gid.to_string())
+                        .or_default()
+                        .push(section_gid.to_string());
+                }
+            }
+        }
+        (section_projects, task_sections)
+    }
+
+    /// Resolve references of tasks and rebuild subtask tree from `parent` references.
+    ///
+    /// Returns tasks and number of references to resources missing from export.
+    pub fn into_tasks(mut self) -> Result<(Vec<asana::Task>, usize), serde_json::Error> {
+        let resources = self.get_resources();
+        let (section_projects, task_sections) = self.get_section_links();
+        let mut unresolved_references = 0;
+        let mut resolve = |value: &Value| -> Value {
+            let gid = match get_reference_gid(value) {
+                Some(gid) => gid,
+                None => return Value::Null,
+            };
+            let mut reference = match value {
+                Value::Object(object) => object.clone(),
+                _ => Map::new(),
+            };
+            reference.insert("gid".to_string(), Value::from(gid));
+            match resources.get(gid) {
+                Some((resource_type, name)) => {
+                    reference
+                        .entry("resource_type")
+                        .or_insert_with(|| Value::from(resource_type.as_str()));
+                    reference
+                        .entry("name")
+                        .or_insert_with(|| Value::from(name.as_str()));
+                }
+                None => unresolved_references += 1,
+            }
+            Value::Object(reference)
+        };
+
+        let mut tasks = Vec::new();
+        for task in self.collections.remove("tasks").unwrap_or_default() {
+            let mut task = match task {
+                Value::Object(task) => task,
+                _ => continue,
+            };
+            let task_gid = task
+                .get("gid")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+
+            for field in REFERENCE_FIELDS {
+                match task.get(field).map(&mut resolve) {
+                    Some(Value::Null) => {
+                        task.remove(field);
+                    }
+                    Some(reference) => {
+                        task.insert(field.to_string(), reference);
+                    }
+                    None => {}
+                }
+            }
+            for field in REFERENCE_LIST_FIELDS {
+                if let Some(Value::Array(values)) = task.get_mut(field) {
+                    *values = values
+                        .iter()
+                        .map(&mut resolve)
+                        .filter(|reference| !reference.is_null())
+                        .collect();
+                }
+            }
+
+            // Memberships refer to project and section, project may be known only from section
+            let mut memberships: Vec<Value> = match task.remove("memberships") {
+                Some(Value::Array(memberships)) => memberships,
+                _ => Vec::new(),
+            };
+            for section_gid in task_sections.get(&task_gid).into_iter().flatten() {
+                memberships.push(Value::from(section_gid.as_str()));
+            }
+            // Resolved projects of memberships, by gid
+            let mut membership_projects: Vec<(String, Value)> = Vec::new();
+            let mut resolved_memberships = Vec::new();
+            for membership in memberships {
+                let (project, section) = match &membership {
+                    Value::Object(object) if object.contains_key("section") => (
+                        object.get("project").cloned(),
+                        object.get("section").cloned().unwrap_or_default(),
+                    ),
+                    // Membership given only by section gid
+                    _ => (None, membership.clone()),
+                };
+                let project = project.or_else(|| {
+                    get_reference_gid(&section)
+                        .and_then(|section_gid| section_projects.get(section_gid))
+                        .map(|project_gid| Value::from(project_gid.as_str()))
+                });
+                let project = match project.map(|project| resolve(&project)) {
+                    Some(Value::Null) | None => continue,
+                    Some(project) => project,
+                };
+                if let Some(project_gid) = get_reference_gid(&project) {
+                    if membership_projects
+                        .iter()
+                        .any(|(gid, _)| gid == project_gid)
+                    {
+                        continue;
+                    }
+                    membership_projects.push((project_gid.to_string(), project.clone()));
+                }
+                let section = match resolve(&section) {
+                    Value::Null => serde_json::to_value(asana::SectionReference::default())?,
+                    section => section,
+                };
+                let mut resolved_membership = Map::new();
+                resolved_membership.insert("project".to_string(), project);
+                resolved_membership.insert("section".to_string(), section);
+                resolved_memberships.push(Value::Object(resolved_membership));
+            }
+            task.insert(
+                "memberships".to_string(),
+                Value::Array(resolved_memberships),
+            );
+            if !task.contains_key("projects") {
+                let projects = membership_projects
+                    .into_iter()
+                    .map(|(_, project)| project)
+                    .collect();
+                task.insert("projects".to_string(), Value::Array(projects));
+            }
+            // Subtasks are rebuilt from `parent` references
+            task.remove("subtasks");
+
+            tasks.push(serde_json::from_value::<asana::Task>(Value::Object(task))?);
+        }
+
+        Ok((nest_subtasks(tasks), unresolved_references))
+    }
+}
@@ -0,0 +1,141 @@
+//! Local files of Asana task attachments
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{asana, taskwarrior};
+
+/// Attachment of task with path of its local file, if it was found.
+#[derive(Clone, Debug)]
+pub struct LocalAttachment {
+    pub attachment: asana::Attachment,
+    pub path: Option<PathBuf>,
+}
+
+/// Get name of attachment usable as file name.
+fn get_file_name(attachment: &asana::Attachment) -> String {
+    let name: String = attachment
+        .name
+        .chars()
+        .map(|c| if c == '/' || c == '\\' { '_' } else { c })
+        .collect();
+    match name.as_str() {
+        "" | "." | ".." => attachment.gid.clone(),
+        _ => name,
+    }
+}
+
+/// Find local files of attachments in directory, either `<attachment gid>/<name>` or `<name>`.
+pub fn find_local_attachments(
+    attachments: HashMap<String, Vec<asana::Attachment>>,
+    directory: &Path,
+) -> HashMap<String, Vec<LocalAttachment>> {
+    attachments
+        .into_iter()
+        .map(|(task_gid, task_attachments)| {
+            let local_attachments = task_attachments
+                .into_iter()
+                .map(|attachment| {
+                    let file_name = get_file_name(&attachment);
+                    let path = [
+                        directory.join(&attachment.gid).join(&file_name),
+                        directory.join(&file_name),
+                    ]
+                    .iter()
+                    .find(|path| path.is_file())
+                    .and_then(|path| std::path::absolute(path).ok());
+                    LocalAttachment { attachment, path }
+                })
+                .collect();
+            (task_gid, local_attachments)
+        })
+        .collect()
+}
+
+/// Get path of attachment copied to directory tree keyed by task UUID:
+/// `<uuid>/<attachment gid>-<name>`, so that attachments with the same name don't overwrite
+/// each other.
+pub fn get_copy_path(copy_directory: &Path, uuid: Uuid, attachment: &asana::Attachment) -> PathBuf {
+    copy_directory.join(uuid.to_string()).join(format!(
+        "{}-{}",
+        attachment.gid,
+        get_file_name(attachment)
+    ))
+}
+
+/// Get `file://` URL of absolute path, escaping characters that are not allowed in URL.
+fn get_file_url(path: &Path) -> String {
+    let mut url = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+/// Get annotation of task with attachment name and path of its local (or copied) file.
+///
+/// Attachments without local file refer to their Asana URL instead, if it is known.
+pub fn get_attachment_annotation(
+    local_attachment: &LocalAttachment,
+    uuid: Uuid,
+    copy_directory: Option<&Path>,
+    task_created_at: DateTime<Utc>,
+) -> taskwarrior::Annotation {
+    let attachment = &local_attachment.attachment;
+    let location = match (&local_attachment.path, copy_directory) {
+        (Some(_), Some(copy_directory)) => {
+            let copy_path = get_copy_path(copy_directory, uuid, attachment);
+            Some(get_file_url(
+                &std::path::absolute(&copy_path).unwrap_or(copy_path),
+            ))
+        }
+        (Some(path), None) => Some(get_file_url(path)),
+        (None, _) => attachment
+            .permanent_url
+            .clone()
+            .or_else(|| attachment.view_url.clone()),
+    };
+    taskwarrior::Annotation {
+        entry: attachment.created_at.unwrap_or(task_created_at),
+        description: match location {
+            Some(location) => format!("{} {}", attachment.name, location),
+            None => attachment.name.clone(),
+        },
+    }
+}
+
+/// Copy local files of attachments to directory tree keyed by task UUID, return number of copied
+/// files.
+pub fn copy_attachments(
+    attachments: &HashMap<String, Vec<LocalAttachment>>,
+    uuids: &HashMap<String, Uuid>,
+    copy_directory: &Path,
+) -> io::Result<usize> {
+    let mut copied = 0;
+    for (task_gid, task_attachments) in attachments {
+        let uuid = match uuids.get(task_gid) {
+            Some(uuid) => *uuid,
+            None => continue,
+        };
+        for local_attachment in task_attachments {
+            if let Some(path) = &local_attachment.path {
+                let copy_path = get_copy_path(copy_directory, uuid, &local_attachment.attachment);
+                if let Some(parent) = copy_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(path, copy_path)?;
+                copied += 1;
+            }
+        }
+    }
+    Ok(copied)
+}
@@ -1,14 +1,214 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    path::PathBuf,
+};
 
-use chrono::{DateTime, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde_json::Value;
 use uuid::Uuid;
 
 use crate::{
     asana::{self},
-    mapping::SectionPriorityMapping,
-    taskwarrior::{self, get_depends_fields, Priority, UUID},
+    attachments::{get_attachment_annotation, LocalAttachment},
+    dependencies::{
+        DanglingDependencyPolicy, Dependency, DependencyCyclePolicy, DependencyGraph,
+        SubtaskDependencies,
+    },
+    hierarchy::{collect_subtask_gids, rebuild_hierarchy},
+    mapping::{AssigneeMapping, AssigneeSectionTarget, SectionPriorityMapping},
+    projects::{
+        get_project_due_date, get_project_owner, is_in_archived_project, ArchivedProjectPolicy,
+        ProjectDueDateTarget, ProjectHierarchy,
+    },
+    taskwarrior::{self, get_depends_fields, Priority, ASANA_ORDER_UDA, PROJECT_OWNER_UDA, UUID},
 };
 
+/// Upper bound of `asana_order` of top-level tasks: integers above it are not exactly
+/// representable as JSON numbers (`f64`).
+const MAX_ASANA_ORDER: u64 = 1 << 53;
+
+/// Options of Asana to Taskwarrior conversion.
+#[derive(Clone, Debug, Default)]
+pub struct ConvertOptions {
+    pub section_priority_mapping: SectionPriorityMapping,
+    /// Positions of sections in their projects by section gid, from sections file.
+    pub section_positions: HashMap<String, usize>,
+    pub subtask_dependencies: SubtaskDependencies,
+    pub append_sections_to_project: bool,
+    pub dangling_dependencies: DanglingDependencyPolicy,
+    pub dependency_cycles: DependencyCyclePolicy,
+    /// Maximum depth of subtasks output as separate tasks, top-level tasks have depth 0.
+    pub max_subtask_depth: Option<usize>,
+    /// Turn subtasks below maximum depth into annotations of their nearest output ancestor.
+    pub collapse_subtasks_into_annotations: bool,
+    /// Stories of tasks by task gid, comments are turned into annotations.
+    pub stories: HashMap<String, Vec<asana::Story>>,
+    /// Turn system stories (assignment changes and so on) into annotations too.
+    pub include_system_stories: bool,
+    /// Attachments of tasks by task gid, turned into annotations.
+    pub attachments: HashMap<String, Vec<LocalAttachment>>,
+    /// Directory that attachment files are copied to, annotations refer to copies.
+    pub attachments_copy_directory: Option<PathBuf>,
+    pub assignee_mapping: AssigneeMapping,
+    /// Set `asana_order` UDA with this stride between sections.
+    pub asana_order_stride: Option<u64>,
+    /// Time that relative dates are resolved against, current time by default.
+    pub reference_time: Option<DateTime<Utc>>,
+    /// Projects by project gid, from projects file.
+    pub projects: HashMap<String, asana::Project>,
+    pub archived_projects: ArchivedProjectPolicy,
+    pub project_due_dates: ProjectDueDateTarget,
+    /// Set `asana_project_owner` UDA to owner of project of task tree, from projects file.
+    pub project_owners: bool,
+    /// Teams and portfolios that project names are prefixed with.
+    pub project_hierarchy: ProjectHierarchy,
+}
+
+impl ConvertOptions {
+    /// Get priority of tasks of section from section priority mapping.
+    pub fn get_section_priority(&self, section: &asana::SectionReference) -> Option<Priority> {
+        self.section_priority_mapping
+            .get_mapping(section, self.section_positions.get(&section.gid).copied())
+    }
+
+    /// Get maximum depth of subtasks output as separate tasks, if it is limited.
+    fn get_max_subtask_depth(&self) -> Option<usize> {
+        if self.collapse_subtasks_into_annotations {
+            Some(self.max_subtask_depth.unwrap_or(0))
+        } else {
+            self.max_subtask_depth
+        }
+    }
+}
+
+/// Error that makes conversion impossible.
+#[derive(Clone, Debug)]
+pub enum ConvertError {
+    /// Dependency cycle, as list of tasks starting and ending with the same task.
+    DependencyCycle(Vec<String>),
+    /// Section (by gid) with more top-level tasks than `asana_order` stride.
+    AsanaOrderStrideExceeded(String, u64),
+    /// Section (by gid) whose `asana_order` is too large to be stored exactly.
+    AsanaOrderOverflow(String),
+}
+
+impl Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::DependencyCycle(cycle) => {
+                write!(f, "Dependency cycle: {}", cycle.join(" -> "))
+            }
+            ConvertError::AsanaOrderStrideExceeded(section_gid, stride) => write!(
+                f,
+                "Section {} has more than {} tasks, increase --asana-order-stride",
+                section_gid, stride
+            ),
+            ConvertError::AsanaOrderOverflow(section_gid) => write!(
+                f,
+                "asana_order of section {} is too large, decrease --asana-order-stride",
+                section_gid
+            ),
+        }
+    }
+}
+
+/// Notes about conversion that user should know about.
+#[derive(Clone, Debug, Default)]
+pub struct ConversionReport {
+    /// Dependencies with task missing from export, and whether they were kept.
+    pub dangling_dependencies: Vec<(Dependency, DanglingDependencyPolicy)>,
+    /// Dependency cycles, and dependencies dropped to break them.
+    pub broken_dependency_cycles: Vec<(Vec<String>, Dependency)>,
+    /// Number of subtasks turned into annotations.
+    pub collapsed_subtasks: usize,
+    /// Number of subtasks skipped because of maximum subtask depth.
+    pub skipped_subtasks: usize,
+    /// Number of attachments whose local files were not found.
+    pub missing_attachment_files: usize,
+    /// Number of top-level copies of tasks that also appear nested or more than once.
+    pub duplicate_tasks: usize,
+    /// Tasks whose parents are missing from export, with gids of parents.
+    pub missing_parents: Vec<(String, String)>,
+    /// Number of top-level tasks in archived projects, and what was done with them.
+    pub archived_project_tasks: usize,
+    pub archived_projects: ArchivedProjectPolicy,
+}
+
+impl Display for ConversionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (dependency, policy) in &self.dangling_dependencies {
+            let action = match policy {
+                DanglingDependencyPolicy::Drop => "dropped",
+                DanglingDependencyPolicy::Keep => "kept",
+                DanglingDependencyPolicy::Placeholder => "replaced with placeholder",
+            };
+            writeln!(
+                f,
+                "Dependency of task {} on task {} refers to task missing from export, {}",
+                dependency.task_gid, dependency.dependency_gid, action
+            )?;
+        }
+        for (cycle, dependency) in &self.broken_dependency_cycles {
+            writeln!(
+                f,
+                "Dependency cycle {} broken by dropping dependency of task {} on task {}",
+                cycle.join(" -> "),
+                dependency.task_gid,
+                dependency.dependency_gid
+            )?;
+        }
+        if self.collapsed_subtasks > 0 {
+            writeln!(
+                f,
+                "{} subtasks turned into annotations",
+                self.collapsed_subtasks
+            )?;
+        }
+        if self.skipped_subtasks > 0 {
+            writeln!(
+                f,
+                "{} subtasks skipped because of maximum subtask depth",
+                self.skipped_subtasks
+            )?;
+        }
+        if self.duplicate_tasks > 0 {
+            writeln!(
+                f,
+                "{} duplicate tasks, only nested or first occurrence is output",
+                self.duplicate_tasks
+            )?;
+        }
+        for (task_gid, parent_gid) in &self.missing_parents {
+            writeln!(
+                f,
+                "Parent {} of task {} is missing from export, task is converted as top-level",
+                parent_gid, task_gid
+            )?;
+        }
+        if self.archived_project_tasks > 0 {
+            let action = match self.archived_projects {
+                ArchivedProjectPolicy::Keep => "kept",
+                ArchivedProjectPolicy::Skip => "skipped with their subtasks",
+                ArchivedProjectPolicy::Complete => "marked completed with their subtasks",
+            };
+            writeln!(
+                f,
+                "{} tasks are only in archived projects, {}",
+                self.archived_project_tasks, action
+            )?;
+        }
+        if self.missing_attachment_files > 0 {
+            writeln!(
+                f,
+                "{} attachments have no local file, annotations refer to Asana instead",
+                self.missing_attachment_files
+            )?;
+        }
+        Ok(())
+    }
+}
+
 pub fn get_uuid_by_gid(uuids: &mut HashMap<String, Uuid>, gid: &str) -> Uuid {
     if let Some(uuid_value) = uuids.get(gid) {
         *uuid_value
@@ -19,23 +219,104 @@ pub fn get_uuid_by_gid(uuids: &mut HashMap<String, Uuid>, gid: &str) -> Uuid {
     }
 }
 
+/// UUID, priority, completion time, project name, depth and project due date of parent task.
 pub type ParentTaskData = (
     Uuid,
     Option<Priority>,
     Option<DateTime<Utc>>,
     Option<String>,
+    usize,
+    Option<DateTime<Utc>>,
 );
 
+/// Find subtasks below maximum depth, map their gids to gids of their nearest output ancestors.
+fn get_hidden_subtasks(
+    asana_tasks: &[asana::Task],
+    depth: usize,
+    max_depth: usize,
+    ancestor_gid: Option<&str>,
+    hidden_subtasks: &mut HashMap<String, String>,
+) {
+    for task in asana_tasks {
+        let output_gid = match ancestor_gid {
+            Some(ancestor_gid) if depth > max_depth => {
+                hidden_subtasks.insert(task.gid.clone(), ancestor_gid.to_string());
+                ancestor_gid
+            }
+            _ => task.gid.as_str(),
+        };
+        if let Some(subtasks) = &task.subtasks {
+            get_hidden_subtasks(
+                subtasks,
+                depth + 1,
+                max_depth,
+                Some(output_gid),
+                hidden_subtasks,
+            );
+        }
+    }
+}
+
+/// Convert Asana subtasks to checklist annotations, including their own subtasks.
+fn get_subtask_annotations(
+    asana_tasks: &[asana::Task],
+    annotations: &mut Vec<taskwarrior::Annotation>,
+) {
+    for task in asana_tasks {
+        if !task.name.is_empty() {
+            let mark = if task.completed { "[x]" } else { "[ ]" };
+            annotations.push(taskwarrior::Annotation {
+                entry: task.created_at,
+                description: format!("{} {}", mark, task.name),
+            });
+        }
+        if let Some(subtasks) = &task.subtasks {
+            get_subtask_annotations(subtasks, annotations);
+        }
+    }
+}
+
+/// Get annotations from stories of task, prefixed with story author.
+fn get_story_annotations(
+    stories: &[asana::Story],
+    include_system_stories: bool,
+    annotations: &mut Vec<taskwarrior::Annotation>,
+) {
+    for story in stories {
+        if story.text.is_empty() || !(include_system_stories || story.is_comment()) {
+            continue;
+        }
+        let description = match &story.created_by {
+            Some(author) if !author.name.is_empty() => format!("{}: {}", author.name, story.text),
+            _ => story.text.clone(),
+        };
+        annotations.push(taskwarrior::Annotation {
+            entry: story.created_at,
+            description,
+        });
+    }
+}
+
+/// Get last millisecond of date, used for Asana dates without time.
+fn get_end_of_day(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&NaiveDateTime::new(
+        date,
+        NaiveTime::from_hms_milli_opt(23, 59, 59, 999).unwrap(),
+    ))
+}
+
 /// Convert Asana task and its subtasks to Taskwarrior, add output tasks to to `output_tasks`
 pub fn convert_tasks_list(
     asana_tasks: Vec<asana::Task>,
     uuids: &mut HashMap<String, Uuid>,
     parent: &Option<ParentTaskData>,
     output_tasks: &mut Vec<taskwarrior::Task>,
-    section_priority_mapping: &SectionPriorityMapping,
-    children_to_dependencies: bool,
-    append_sections_to_project: bool,
-) -> Option<()> {
+    options: &ConvertOptions,
+    dependency_graph: &DependencyGraph,
+    report: &mut ConversionReport,
+) -> Result<(), ConvertError> {
+    let depth = parent.as_ref().map_or(0, |parent| parent.4 + 1);
+
     for task in asana_tasks {
         let uuid = get_uuid_by_gid(uuids, &task.gid);
 
@@ -48,64 +329,139 @@ pub fn convert_tasks_list(
         let membership = task.memberships.first(); // TODO
 
         let priority: Option<Priority>;
-        if let Some((_, parent_priority, _, _)) = parent {
+        if let Some((_, parent_priority, _, _, _, _)) = parent {
             priority = parent_priority.clone();
         } else if let Some(membership) = membership {
-            priority = section_priority_mapping.get_mapping(&membership.section.name);
+            priority = options.get_section_priority(&membership.section);
         } else {
             priority = None;
         }
 
+        // Project without name (trimmed from export) is not output
+        let project_membership =
+            membership.filter(|membership| !membership.project.name.is_empty());
         let project_name;
-        if let Some((_, _, _, Some(parent_project_name))) = parent {
+        if let Some((_, _, _, Some(parent_project_name), _, _)) = parent {
             project_name = Some(parent_project_name.clone());
-        } else if append_sections_to_project {
-            project_name = membership.map(|membership| {
-                membership.project.name.clone() + ". " + &membership.section.name
+        } else if options.append_sections_to_project {
+            project_name = project_membership.map(|membership| {
+                options
+                    .project_hierarchy
+                    .get_section_project_name(&membership.project, &membership.section)
             });
         } else {
-            project_name = membership.map(|membership| membership.project.name.clone());
+            project_name = project_membership.map(|membership| {
+                options
+                    .project_hierarchy
+                    .get_project_name(&membership.project)
+            });
         }
 
         let parent_value = parent.as_ref().map(|parent| UUID::new(parent.0));
-        let tags = Some(task.tags.iter().map(|tag| tag.name.clone()).collect());
+        let mut tags: Vec<String> = task.tags.iter().map(|tag| tag.name.clone()).collect();
 
-        let due = task.due_on.map(|due_date| {
-            Utc.from_utc_datetime(&NaiveDateTime::new(
-                due_date,
-                NaiveTime::from_hms_milli_opt(23, 59, 59, 999).unwrap(),
-            ))
-        });
+        let now = options.reference_time.unwrap_or_else(Utc::now);
+        let status_mapping = options
+            .assignee_mapping
+            .status_mapping
+            .get(&task.assignee_status);
+        let scheduled = status_mapping
+            .and_then(|status_mapping| status_mapping.scheduled.as_ref())
+            .map(|scheduled| scheduled.resolve(now));
+        let wait = status_mapping
+            .and_then(|status_mapping| status_mapping.wait.as_ref())
+            .map(|wait| wait.resolve(now));
+        if let Some(status_mapping) = status_mapping {
+            tags.extend(status_mapping.tags.iter().cloned());
+        }
+        let assignee_section_name = task
+            .assignee_section
+            .as_ref()
+            .map(|section| section.name.clone())
+            .filter(|name| !name.is_empty());
+        let project_name = match (options.assignee_mapping.section_target, project_name) {
+            (AssigneeSectionTarget::Tag, project_name) => {
+                tags.extend(assignee_section_name);
+                project_name
+            }
+            (AssigneeSectionTarget::Project, None) => assignee_section_name,
+            (_, project_name) => project_name,
+        };
+        let tags = Some(tags);
+
+        // Subtasks are in project of their top-level task, so they get its due date too
+        let project_due_date = match parent {
+            Some((_, _, _, _, _, Some(parent_project_due_date))) => Some(*parent_project_due_date),
+            _ => get_project_due_date(&task, &options.projects).map(get_end_of_day),
+        };
+        let due = task.due_on.map(get_end_of_day);
+        let project_due = match (task.due_on, task.due_at) {
+            (None, None) => project_due_date,
+            _ => None,
+        };
+        let (due, until) = match options.project_due_dates {
+            ProjectDueDateTarget::None => (due, None),
+            ProjectDueDateTarget::Due => (due.or(project_due), None),
+            ProjectDueDateTarget::Until => (due, project_due),
+        };
 
         let completed_at: Option<DateTime<Utc>>;
-        if let Some((_, _, Some(parent_completed_at), _)) = parent {
+        if let Some((_, _, Some(parent_completed_at), _, _, _)) = parent {
             completed_at = Some(*parent_completed_at);
         } else {
             completed_at = task.completed_at;
         }
 
-        let asana_dependencies = task
-            .dependencies
-            .map(|dependencies| {
-                dependencies
-                    .iter()
-                    .map(|dependency| get_uuid_by_gid(uuids, &dependency.gid))
-                    .collect()
+        let dependencies_tmp: Vec<Uuid> = dependency_graph
+            .get_dependencies(&task.gid)
+            .into_iter()
+            .filter(|dependency_gid| {
+                dependency_graph.gids.contains(*dependency_gid)
+                    || options.dangling_dependencies != DanglingDependencyPolicy::Drop
             })
-            .unwrap_or_else(Vec::new);
+            .map(|dependency_gid| get_uuid_by_gid(uuids, dependency_gid))
+            .collect();
+        let dependencies = if dependencies_tmp.is_empty() {
+            None
+        } else {
+            Some(dependencies_tmp)
+        };
 
-        let mut dependencies_tmp: Vec<Uuid> = asana_dependencies;
-        if children_to_dependencies {
-            if let Some(subtasks) = &task.subtasks {
-                for subtask in subtasks {
-                    dependencies_tmp.push(get_uuid_by_gid(uuids, &subtask.gid));
+        let mut subtasks = task.subtasks;
+        let mut annotations = Vec::new();
+        if let Some(stories) = options.stories.get(&task.gid) {
+            get_story_annotations(stories, options.include_system_stories, &mut annotations);
+        }
+        for local_attachment in options.attachments.get(&task.gid).into_iter().flatten() {
+            if local_attachment.path.is_none() {
+                report.missing_attachment_files += 1;
+            }
+            annotations.push(get_attachment_annotation(
+                local_attachment,
+                uuid,
+                options.attachments_copy_directory.as_deref(),
+                task.created_at,
+            ));
+        }
+        if let (Some(max_depth), Some(hidden_subtasks)) =
+            (options.get_max_subtask_depth(), &subtasks)
+        {
+            if depth >= max_depth {
+                let mut subtask_annotations = Vec::new();
+                get_subtask_annotations(hidden_subtasks, &mut subtask_annotations);
+                if options.collapse_subtasks_into_annotations {
+                    report.collapsed_subtasks += subtask_annotations.len();
+                    annotations.append(&mut subtask_annotations);
+                } else {
+                    report.skipped_subtasks += subtask_annotations.len();
                 }
+                subtasks = None;
             }
         }
-        let dependencies = if dependencies_tmp.is_empty() {
+        let annotations = if annotations.is_empty() {
             None
         } else {
-            Some(dependencies_tmp)
+            Some(annotations)
         };
 
         if let Some(completed_at) = completed_at {
@@ -117,10 +473,10 @@ pub fn convert_tasks_list(
                 start: task.start_on,
                 end: Some(completed_at),
                 due,
-                until: None,
+                until,
                 wait: None,
                 modified: None,
-                scheduled: None,
+                scheduled,
                 recur: None,
                 mask: None,
                 imask: None,
@@ -129,21 +485,27 @@ pub fn convert_tasks_list(
                 priority: priority.clone(),
                 depends: dependencies.map(get_depends_fields),
                 tags,
-                annotation: None,
+                annotations: annotations.clone(),
+                udas: BTreeMap::new(),
+                asana_gid: Some(task.gid.clone()),
             };
         } else {
+            let status = match wait {
+                Some(wait) if wait > now => taskwarrior::Status::Waiting,
+                _ => taskwarrior::Status::Pending,
+            };
             taskwarrior_task = taskwarrior::Task {
-                status: taskwarrior::Status::Pending,
+                status,
                 uuid: UUID::new(uuid),
                 entry: task.created_at,
                 description: task.name,
                 start: task.start_on,
                 end: None,
                 due,
-                until: None,
-                wait: None,
+                until,
+                wait,
                 modified: None,
-                scheduled: None,
+                scheduled,
                 recur: None,
                 mask: None,
                 imask: None,
@@ -152,47 +514,366 @@ pub fn convert_tasks_list(
                 priority: priority.clone(),
                 depends: dependencies.map(get_depends_fields),
                 tags,
-                annotation: None,
+                annotations: annotations.clone(),
+                udas: BTreeMap::new(),
+                asana_gid: Some(task.gid.clone()),
             };
         }
 
         output_tasks.push(taskwarrior_task);
 
-        if let Some(subtasks) = task.subtasks {
+        if let Some(subtasks) = subtasks {
             convert_tasks_list(
                 subtasks,
                 uuids,
-                &Some((uuid, priority, completed_at, project_name)),
+                &Some((
+                    uuid,
+                    priority,
+                    completed_at,
+                    project_name,
+                    depth,
+                    project_due_date,
+                )),
                 output_tasks,
-                section_priority_mapping,
-                children_to_dependencies,
-                append_sections_to_project,
+                options,
+                dependency_graph,
+                report,
             )?;
         }
     }
 
-    Some(())
+    Ok(())
+}
+
+/// Create placeholder Taskwarrior task for Asana task missing from export.
+fn get_placeholder_task(
+    gid: &str,
+    uuids: &mut HashMap<String, Uuid>,
+    entry: DateTime<Utc>,
+    dependencies: Vec<Uuid>,
+) -> taskwarrior::Task {
+    taskwarrior::Task {
+        status: taskwarrior::Status::Pending,
+        uuid: UUID::new(get_uuid_by_gid(uuids, gid)),
+        entry,
+        description: format!("Asana task {} (missing from export)", gid),
+        start: None,
+        end: None,
+        due: None,
+        until: None,
+        wait: None,
+        modified: None,
+        scheduled: None,
+        recur: None,
+        mask: None,
+        imask: None,
+        parent: None,
+        project: None,
+        priority: None,
+        depends: if dependencies.is_empty() {
+            None
+        } else {
+            Some(get_depends_fields(dependencies))
+        },
+        tags: Some(vec!["placeholder".to_string()]),
+        annotations: None,
+        udas: BTreeMap::new(),
+        asana_gid: Some(gid.to_string()),
+    }
 }
 
 /// Convert Asana tasks to Taskwarrior tasks.
 pub fn convert_tasks(
     asana_tasks: Vec<asana::Task>,
-    section_priority_mapping: &SectionPriorityMapping,
-    children_to_dependencies: bool,
-    append_sections_to_project: bool,
-) -> Option<Vec<taskwarrior::Task>> {
+    options: &ConvertOptions,
+    report: &mut ConversionReport,
+) -> Result<Vec<taskwarrior::Task>, ConvertError> {
     let mut uuids = HashMap::new();
+    convert_tasks_with_uuids(asana_tasks, &mut uuids, options, report)
+}
+
+/// Conversion of Asana tasks in two passes, so that task trees can be read one by one.
+///
+/// On the first pass all task trees are added with `add_task_tree`, then `prepare` resolves
+/// dependencies, then on the second pass task trees are converted with `convert_task_tree`, and
+/// finally `finish` outputs tasks that do not come from export.
+pub struct Converter<'a> {
+    options: &'a ConvertOptions,
+    pub uuids: HashMap<String, Uuid>,
+    dependency_graph: DependencyGraph,
+    hidden_subtasks: HashMap<String, String>,
+    /// Hidden subtasks of each top-level task tree, merged once duplicate trees are known.
+    tree_hidden_subtasks: Vec<(String, HashMap<String, String>)>,
+    /// Gids of tasks that appear as subtasks, their top-level copies are dropped.
+    nested_gids: HashSet<String>,
+    /// Gids of converted top-level tasks, later copies are dropped.
+    converted_gids: HashSet<String>,
+    /// Sections missing from sections file, in order of first appearance.
+    unknown_sections: Vec<String>,
+    /// Number of converted top-level tasks of each section.
+    section_task_counts: HashMap<String, usize>,
+}
+
+impl<'a> Converter<'a> {
+    pub fn new(options: &'a ConvertOptions, uuids: HashMap<String, Uuid>) -> Self {
+        Converter {
+            options,
+            uuids,
+            dependency_graph: DependencyGraph::default(),
+            hidden_subtasks: HashMap::new(),
+            tree_hidden_subtasks: Vec::new(),
+            nested_gids: HashSet::new(),
+            converted_gids: HashSet::new(),
+            unknown_sections: Vec::new(),
+            section_task_counts: HashMap::new(),
+        }
+    }
+
+    /// Get index of section on Asana board, sections missing from sections file go after others.
+    fn get_section_index(&mut self, section_gid: &str) -> usize {
+        if let Some(&position) = self.options.section_positions.get(section_gid) {
+            return position;
+        }
+        let known_sections_count = self
+            .options
+            .section_positions
+            .values()
+            .max()
+            .map_or(0, |max_position| max_position + 1);
+        let unknown_index = match self
+            .unknown_sections
+            .iter()
+            .position(|gid| gid == section_gid)
+        {
+            Some(index) => index,
+            None => {
+                self.unknown_sections.push(section_gid.to_string());
+                self.unknown_sections.len() - 1
+            }
+        };
+        known_sections_count + unknown_index
+    }
+
+    /// Set `asana_order` UDA of converted task tree: top-level task gets
+    /// `section index × stride + position in section`, its subtasks are ordered between it and
+    /// the next task.
+    fn set_asana_order(
+        &mut self,
+        section_gid: &str,
+        stride: u64,
+        output_tasks: &mut [taskwarrior::Task],
+    ) -> Result<(), ConvertError> {
+        let section_index = self.get_section_index(section_gid) as u64;
+        let position = self
+            .section_task_counts
+            .entry(section_gid.to_string())
+            .or_default();
+        if *position as u64 >= stride {
+            return Err(ConvertError::AsanaOrderStrideExceeded(
+                section_gid.to_string(),
+                stride,
+            ));
+        }
+        let order = section_index
+            .checked_mul(stride)
+            .and_then(|order| order.checked_add(*position as u64))
+            .filter(|&order| order < MAX_ASANA_ORDER)
+            .ok_or_else(|| ConvertError::AsanaOrderOverflow(section_gid.to_string()))?
+            as f64;
+        *position += 1;
+
+        let tree_size = output_tasks.len();
+        for (index, task) in output_tasks.iter_mut().enumerate() {
+            let task_order = order + index as f64 / tree_size as f64;
+            task.udas
+                .insert(ASANA_ORDER_UDA.to_string(), Value::from(task_order));
+        }
+        Ok(())
+    }
+
+    /// Add Asana task and its subtasks to dependency graph.
+    pub fn add_task_tree(&mut self, task: &asana::Task) {
+        let tasks = std::slice::from_ref(task);
+        let mut subtask_gids = HashSet::new();
+        collect_subtask_gids(tasks, &mut subtask_gids);
+        self.nested_gids
+            .extend(subtask_gids.into_iter().map(str::to_string));
+        if self.is_skipped(task) {
+            return;
+        }
+        self.dependency_graph
+            .add_task_trees(tasks, self.options.subtask_dependencies);
+        if let Some(max_depth) = self.options.get_max_subtask_depth() {
+            let mut hidden_subtasks = HashMap::new();
+            get_hidden_subtasks(tasks, 0, max_depth, None, &mut hidden_subtasks);
+            if !hidden_subtasks.is_empty() {
+                self.tree_hidden_subtasks
+                    .push((task.gid.clone(), hidden_subtasks));
+            }
+        }
+    }
+
+    /// Check if top-level task is a copy of task exported nested or earlier, as it happens in
+    /// streaming mode where hierarchy is not rebuilt.
+    fn is_duplicate(&mut self, task: &asana::Task) -> bool {
+        self.nested_gids.contains(&task.gid) || !self.converted_gids.insert(task.gid.clone())
+    }
+
+    /// Resolve dependency cycles and find dangling dependencies after all task trees are added.
+    pub fn prepare(&mut self, report: &mut ConversionReport) -> Result<(), ConvertError> {
+        // Top-level copies of nested tasks would hide their subtasks under wrong ancestors
+        for (gid, hidden_subtasks) in self.tree_hidden_subtasks.drain(..) {
+            if !self.nested_gids.contains(&gid) {
+                self.hidden_subtasks.extend(hidden_subtasks);
+            }
+        }
+        // Dependencies on subtasks that are not output are moved to their output ancestors
+        self.dependency_graph.merge_tasks(&self.hidden_subtasks);
+
+        while let Some(cycle) = self.dependency_graph.find_cycle() {
+            let cycle_description = self.dependency_graph.describe_cycle(&cycle);
+            match self.options.dependency_cycles {
+                DependencyCyclePolicy::Fail => {
+                    return Err(ConvertError::DependencyCycle(cycle_description))
+                }
+                DependencyCyclePolicy::Break => {
+                    // Dependencies are stored in order of addition
+                    let last_index = *cycle.iter().max().unwrap();
+                    let dependency = self.dependency_graph.remove_edge(last_index);
+                    report
+                        .broken_dependency_cycles
+                        .push((cycle_description, dependency));
+                }
+            }
+        }
+
+        for dependency in self.dependency_graph.get_dangling() {
+            report
+                .dangling_dependencies
+                .push((dependency.clone(), self.options.dangling_dependencies));
+        }
+
+        Ok(())
+    }
+
+    /// Check if task tree is not output because it is only in archived projects.
+    fn is_skipped(&self, task: &asana::Task) -> bool {
+        self.options.archived_projects == ArchivedProjectPolicy::Skip
+            && is_in_archived_project(task, &self.options.projects)
+    }
+
+    /// Convert Asana task and its subtasks, add output tasks to `output_tasks`.
+    pub fn convert_task_tree(
+        &mut self,
+        mut task: asana::Task,
+        output_tasks: &mut Vec<taskwarrior::Task>,
+        report: &mut ConversionReport,
+    ) -> Result<(), ConvertError> {
+        if self.is_duplicate(&task) {
+            report.duplicate_tasks += 1;
+            return Ok(());
+        }
+        if is_in_archived_project(&task, &self.options.projects) {
+            report.archived_project_tasks += 1;
+            report.archived_projects = self.options.archived_projects;
+            match self.options.archived_projects {
+                ArchivedProjectPolicy::Keep => {}
+                ArchivedProjectPolicy::Skip => return Ok(()),
+                ArchivedProjectPolicy::Complete => {
+                    if task.completed_at.is_none() {
+                        // Archiving time is unknown, last modification of task is the best guess;
+                        // `modified_at` missing from trimmed export falls back to creation time
+                        task.completed = true;
+                        task.completed_at = Some(task.modified_at.max(task.created_at));
+                    }
+                }
+            }
+        }
+        let section_gid = task
+            .memberships
+            .first()
+            .map(|membership| membership.section.gid.clone())
+            .unwrap_or_default();
+        // Subtasks are in project of their top-level task, so they get its owner too
+        let project_owner = get_project_owner(&task, &self.options.projects)
+            .filter(|_| self.options.project_owners)
+            .map(Value::from);
+        let first_index = output_tasks.len();
+        convert_tasks_list(
+            vec![task],
+            &mut self.uuids,
+            &None,
+            output_tasks,
+            self.options,
+            &self.dependency_graph,
+            report,
+        )?;
+        if let Some(stride) = self.options.asana_order_stride {
+            self.set_asana_order(&section_gid, stride, &mut output_tasks[first_index..])?;
+        }
+        if let Some(project_owner) = project_owner {
+            for task in &mut output_tasks[first_index..] {
+                task.udas
+                    .insert(PROJECT_OWNER_UDA.to_string(), project_owner.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Add tasks that are not converted from Asana tasks to `output_tasks`.
+    pub fn finish(&mut self, output_tasks: &mut Vec<taskwarrior::Task>) {
+        if self.options.dangling_dependencies != DanglingDependencyPolicy::Placeholder {
+            return;
+        }
+
+        let dependency_graph = &self.dependency_graph;
+        let uuids = &mut self.uuids;
+        let mut missing_gids: Vec<&str> = Vec::new();
+        for dependency in dependency_graph.get_dangling() {
+            for gid in [&dependency.task_gid, &dependency.dependency_gid] {
+                if !dependency_graph.gids.contains(gid) && !missing_gids.contains(&gid.as_str()) {
+                    missing_gids.push(gid);
+                }
+            }
+        }
+        for gid in missing_gids {
+            let dependencies = dependency_graph
+                .get_dependencies(gid)
+                .into_iter()
+                .map(|dependency_gid| get_uuid_by_gid(uuids, dependency_gid))
+                .collect();
+            // Placeholder gets fixed creation time, so that it does not change between conversions
+            let entry = dependency_graph
+                .get_placeholder_entry(gid)
+                .unwrap_or(DateTime::UNIX_EPOCH);
+            output_tasks.push(get_placeholder_task(gid, uuids, entry, dependencies));
+        }
+    }
+}
+
+/// Convert Asana tasks to Taskwarrior tasks, reusing and extending existing gid-to-UUID mapping.
+pub fn convert_tasks_with_uuids(
+    asana_tasks: Vec<asana::Task>,
+    uuids: &mut HashMap<String, Uuid>,
+    options: &ConvertOptions,
+    report: &mut ConversionReport,
+) -> Result<Vec<taskwarrior::Task>, ConvertError> {
     let mut output_tasks = Vec::new();
+    let asana_tasks = rebuild_hierarchy(asana_tasks, report);
+
+    let mut converter = Converter::new(options, std::mem::take(uuids));
+    for task in &asana_tasks {
+        converter.add_task_tree(task);
+    }
+    let result = converter.prepare(report).and_then(|_| {
+        for task in asana_tasks {
+            converter.convert_task_tree(task, &mut output_tasks, report)?;
+        }
+        converter.finish(&mut output_tasks);
+        Ok(())
+    });
+    *uuids = converter.uuids;
+    result?;
 
-    convert_tasks_list(
-        asana_tasks,
-        &mut uuids,
-        &None,
-        &mut output_tasks,
-        section_priority_mapping,
-        children_to_dependencies,
-        append_sections_to_project,
-    )?;
-
-    Some(output_tasks)
+    Ok(output_tasks)
 }
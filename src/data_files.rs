@@ -0,0 +1,297 @@
+//! Writing Taskwarrior tasks into data files of Taskwarrior 2.x (`pending.data` and
+//! `completed.data` in FF4 format)
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::taskwarrior::{self, get_task_properties, InvalidDependency};
+
+pub const PENDING_FILE_NAME: &str = "pending.data";
+pub const COMPLETED_FILE_NAME: &str = "completed.data";
+
+#[derive(Debug)]
+pub enum DataFilesError {
+    Io(PathBuf, io::Error),
+    Locked(PathBuf),
+    InvalidDependency(InvalidDependency),
+}
+
+impl From<InvalidDependency> for DataFilesError {
+    fn from(err: InvalidDependency) -> Self {
+        DataFilesError::InvalidDependency(err)
+    }
+}
+
+impl Display for DataFilesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataFilesError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            DataFilesError::Locked(path) => write!(
+                f,
+                "{}: file is locked, probably by running Taskwarrior",
+                path.display()
+            ),
+            DataFilesError::InvalidDependency(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Numbers of tasks added to data files and tasks replaced in them.
+#[derive(Clone, Debug, Default)]
+pub struct DataFilesReport {
+    pub added_tasks: usize,
+    pub replaced_tasks: usize,
+}
+
+impl Display for DataFilesReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} tasks added, {} tasks replaced in Taskwarrior data files",
+            self.added_tasks, self.replaced_tasks
+        )
+    }
+}
+
+/// Escape attribute value as Taskwarrior 2.x does: JSON string escapes, then brackets.
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            '[' => escaped.push_str("&open;"),
+            ']' => escaped.push_str("&close;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Get attributes of task in Taskwarrior 2.x representation: `depends` and `tags` are
+/// comma-joined, annotations are `annotation_<epoch>` attributes.
+fn get_task_attributes(
+    task: &taskwarrior::Task,
+) -> Result<BTreeMap<String, String>, DataFilesError> {
+    let properties = get_task_properties(task)?;
+    let mut attributes = BTreeMap::new();
+    let mut depends = Vec::new();
+    let mut tags = Vec::new();
+    for (name, value) in properties {
+        if let Some(dependency) = name.strip_prefix("dep_") {
+            depends.push(dependency.to_string());
+        } else if let Some(tag) = name.strip_prefix("tag_") {
+            tags.push(tag.to_string());
+        } else if !value.is_empty() {
+            attributes.insert(name, value);
+        }
+    }
+    if !depends.is_empty() {
+        attributes.insert("depends".to_string(), depends.join(","));
+    }
+    if !tags.is_empty() {
+        attributes.insert("tags".to_string(), tags.join(","));
+    }
+    // Taskwarrior 2.x still has waiting status
+    if task.status == taskwarrior::Status::Waiting {
+        attributes.insert("status".to_string(), "waiting".to_string());
+    }
+    attributes.insert("uuid".to_string(), task.uuid.uuid.to_string());
+    Ok(attributes)
+}
+
+/// Get FF4 line of task: `[name:"value" name:"value"]`, attributes sorted by name.
+pub fn get_f4_line(task: &taskwarrior::Task) -> Result<String, DataFilesError> {
+    let attributes: Vec<String> = get_task_attributes(task)?
+        .iter()
+        .map(|(name, value)| format!("{}:\"{}\"", name, escape_value(value)))
+        .collect();
+    Ok(format!("[{}]", attributes.join(" ")))
+}
+
+/// Get UUID of task from its FF4 line.
+fn get_line_uuid(line: &str) -> Option<String> {
+    let start = ["[uuid:\"", " uuid:\""]
+        .iter()
+        .find_map(|prefix| line.find(prefix).map(|index| index + prefix.len()))?;
+    let length = line[start..].find('"')?;
+    Some(line[start..start + length].to_lowercase())
+}
+
+/// Check if task goes to `completed.data` rather than `pending.data`.
+fn is_completed(task: &taskwarrior::Task) -> bool {
+    matches!(
+        task.status,
+        taskwarrior::Status::Completed | taskwarrior::Status::Deleted
+    )
+}
+
+/// `fcntl` command to set lock without waiting. On Linux open file description locks are used:
+/// they conflict with record locks of other processes, but belong to the open file rather than
+/// to the process, so they are not released when another descriptor of the same file is closed.
+#[cfg(target_os = "linux")]
+const SET_LOCK_COMMAND: libc::c_int = libc::F_OFD_SETLK;
+#[cfg(all(unix, not(target_os = "linux")))]
+const SET_LOCK_COMMAND: libc::c_int = libc::F_SETLK;
+
+/// Try to lock whole file for writing with `fcntl`, as Taskwarrior 2.x locks its data files.
+/// Returns `false` if file is already locked.
+#[cfg(unix)]
+pub fn try_lock(file: &File) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: `flock` is a plain C struct, all-zero is a valid value for it (whole file, no pid)
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    // SAFETY: descriptor is open while `file` is borrowed, `lock` outlives the call
+    if unsafe { libc::fcntl(file.as_raw_fd(), SET_LOCK_COMMAND, &lock) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EACCES) | Some(libc::EAGAIN) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+/// Try to lock file where `fcntl` is not available.
+#[cfg(not(unix))]
+pub fn try_lock(file: &File) -> io::Result<bool> {
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(fs::TryLockError::WouldBlock) => Ok(false),
+        Err(fs::TryLockError::Error(err)) => Err(err),
+    }
+}
+
+/// Data file of Taskwarrior, locked while it is rewritten.
+///
+/// Lock is released when `file` is closed.
+struct DataFile {
+    path: PathBuf,
+    file: File,
+    lines: Vec<String>,
+}
+
+impl DataFile {
+    /// Open (or create) and lock data file, read its lines.
+    fn open(path: PathBuf) -> Result<Self, DataFilesError> {
+        let io_error = |err| DataFilesError::Io(path.clone(), err);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(io_error)?;
+        if !try_lock(&file).map_err(io_error)? {
+            return Err(DataFilesError::Locked(path));
+        }
+        let lines = BufReader::new(&file)
+            .lines()
+            .collect::<Result<Vec<String>, io::Error>>()
+            .map_err(io_error)?;
+        Ok(DataFile { path, file, lines })
+    }
+
+    /// Rewrite data file in place through its locked descriptor, as Taskwarrior 2.x does, then
+    /// release the lock. The file keeps its inode, so processes waiting for the lock read the new
+    /// contents rather than a replaced file.
+    fn write(self) -> Result<(), DataFilesError> {
+        let io_error = |err| DataFilesError::Io(self.path.clone(), err);
+        let mut file = &self.file;
+        file.set_len(0).map_err(io_error)?;
+        file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        let mut writer = BufWriter::new(file);
+        for line in &self.lines {
+            writeln!(writer, "{}", line).map_err(io_error)?;
+        }
+        writer
+            .into_inner()
+            .map_err(|err| io_error(err.into_error()))?
+            .sync_all()
+            .map_err(io_error)?;
+        drop(self.file);
+        Ok(())
+    }
+}
+
+/// Merge tasks into `pending.data` and `completed.data` in Taskwarrior 2.x data directory.
+///
+/// Lines of tasks with the same UUID are replaced (or moved to the other file if status
+/// changed), new tasks are appended, other lines are kept as they are. Both files are locked
+/// while they are rewritten in place.
+///
+/// The two files can't be rewritten together: `completed.data` is written first, so if writing
+/// `pending.data` fails, tasks completed since previous conversion are left in both files rather
+/// than lost, while tasks reopened since then are missing from both until conversion is rerun.
+pub fn write_data_files(
+    directory: &Path,
+    tasks: &[taskwarrior::Task],
+) -> Result<DataFilesReport, DataFilesError> {
+    fs::create_dir_all(directory).map_err(|err| DataFilesError::Io(directory.into(), err))?;
+    let mut pending = DataFile::open(directory.join(PENDING_FILE_NAME))?;
+    let mut completed = DataFile::open(directory.join(COMPLETED_FILE_NAME))?;
+
+    let mut new_lines: HashMap<String, (String, bool)> = HashMap::new();
+    for task in tasks {
+        new_lines.insert(
+            task.uuid.uuid.to_string(),
+            (get_f4_line(task)?, is_completed(task)),
+        );
+    }
+
+    // Lines are replaced in place, so that IDs of pending tasks are kept
+    let mut replaced_uuids = HashSet::new();
+    let mut written_uuids = HashSet::new();
+    for (data_file, is_completed_file) in [(&mut pending, false), (&mut completed, true)] {
+        data_file.lines = data_file
+            .lines
+            .drain(..)
+            .filter_map(|line| {
+                let uuid = match get_line_uuid(&line) {
+                    Some(uuid) if new_lines.contains_key(&uuid) => uuid,
+                    _ => return Some(line),
+                };
+                let (new_line, is_completed) = &new_lines[&uuid];
+                replaced_uuids.insert(uuid.clone());
+                if *is_completed == is_completed_file && written_uuids.insert(uuid) {
+                    Some(new_line.clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+    for task in tasks {
+        let uuid = task.uuid.uuid.to_string();
+        if written_uuids.insert(uuid.clone()) {
+            let (line, is_completed) = &new_lines[&uuid];
+            let data_file = if *is_completed {
+                &mut completed
+            } else {
+                &mut pending
+            };
+            data_file.lines.push(line.clone());
+        }
+    }
+    let report = DataFilesReport {
+        added_tasks: new_lines.len() - replaced_uuids.len(),
+        replaced_tasks: replaced_uuids.len(),
+    };
+
+    completed.write()?;
+    pending.write()?;
+    Ok(report)
+}
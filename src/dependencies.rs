@@ -0,0 +1,297 @@
+//! Dependency graph of Asana tasks
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use itertools::Itertools;
+
+use crate::asana;
+
+/// What to do with dependencies on tasks that are missing from export.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DanglingDependencyPolicy {
+    /// Do not output such dependencies.
+    Drop,
+    /// Keep such dependencies, pointing to UUIDs of tasks that do not exist.
+    #[default]
+    Keep,
+    /// Output placeholder tasks in place of missing tasks.
+    Placeholder,
+}
+
+/// What to do with dependency cycles.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DependencyCyclePolicy {
+    /// Stop conversion with error.
+    Fail,
+    /// Drop the most recently added dependency of each cycle.
+    #[default]
+    Break,
+}
+
+/// How subtasks are turned into dependencies.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SubtaskDependencies {
+    /// Do not add dependencies for subtasks.
+    #[default]
+    None,
+    /// Parent task depends on all of its subtasks.
+    ParentBlockedByChildren,
+    /// Each subtask depends on its parent task.
+    ChildrenBlockedByParent,
+    /// Each subtask depends on the previous subtask of the same parent.
+    Sequential,
+}
+
+/// Dependency between Asana tasks, `task_gid` depends on `dependency_gid`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Dependency {
+    pub task_gid: String,
+    pub dependency_gid: String,
+}
+
+/// Dependencies between Asana tasks, merged from `dependencies`, `dependents` and subtasks.
+#[derive(Clone, Debug, Default)]
+pub struct DependencyGraph {
+    /// Gids of all tasks present in export.
+    pub gids: HashSet<String>,
+    /// Names of tasks present in export.
+    names: HashMap<String, String>,
+    /// Creation times of tasks in export, including tasks that are not output.
+    entries: HashMap<String, DateTime<Utc>>,
+    /// Dependencies in order of addition.
+    edges: Vec<Dependency>,
+    edges_set: HashSet<Dependency>,
+    /// Dependencies of each task, in order of addition.
+    adjacency: HashMap<String, Vec<String>>,
+}
+
+impl DependencyGraph {
+    /// Build dependency graph of Asana tasks and their subtasks.
+    pub fn build(asana_tasks: &[asana::Task], subtask_dependencies: SubtaskDependencies) -> Self {
+        let mut graph = DependencyGraph::default();
+        graph.add_task_trees(asana_tasks, subtask_dependencies);
+        graph
+    }
+
+    /// Add Asana tasks and their subtasks to graph, dependencies may refer to tasks added later.
+    pub fn add_task_trees(
+        &mut self,
+        asana_tasks: &[asana::Task],
+        subtask_dependencies: SubtaskDependencies,
+    ) {
+        self.add_gids(asana_tasks);
+        self.add_tasks(asana_tasks, subtask_dependencies);
+    }
+
+    fn add_gids(&mut self, asana_tasks: &[asana::Task]) {
+        for task in asana_tasks {
+            self.entries.insert(task.gid.clone(), task.created_at);
+            // Tasks without name are not output together with their subtasks, dependencies on
+            // them are dangling
+            if task.name.is_empty() {
+                continue;
+            }
+            self.gids.insert(task.gid.clone());
+            self.names.insert(task.gid.clone(), task.name.clone());
+            if let Some(subtasks) = &task.subtasks {
+                self.add_gids(subtasks);
+            }
+        }
+    }
+
+    fn add_tasks(
+        &mut self,
+        asana_tasks: &[asana::Task],
+        subtask_dependencies: SubtaskDependencies,
+    ) {
+        for task in asana_tasks {
+            if let Some(dependencies) = &task.dependencies {
+                for dependency in dependencies {
+                    self.add_edge(&task.gid, &dependency.gid);
+                }
+            }
+            if let Some(dependents) = &task.dependents {
+                for dependent in dependents {
+                    self.add_edge(&dependent.gid, &task.gid);
+                }
+            }
+            if let Some(subtasks) = &task.subtasks {
+                match subtask_dependencies {
+                    SubtaskDependencies::None => {}
+                    SubtaskDependencies::ParentBlockedByChildren => {
+                        for subtask in subtasks {
+                            self.add_edge(&task.gid, &subtask.gid);
+                        }
+                    }
+                    SubtaskDependencies::ChildrenBlockedByParent => {
+                        for subtask in subtasks {
+                            self.add_edge(&subtask.gid, &task.gid);
+                        }
+                    }
+                    SubtaskDependencies::Sequential => {
+                        for (previous, subtask) in subtasks.iter().tuple_windows() {
+                            self.add_edge(&subtask.gid, &previous.gid);
+                        }
+                    }
+                }
+                self.add_tasks(subtasks, subtask_dependencies);
+            }
+        }
+    }
+
+    /// Add dependency, ignoring duplicates.
+    pub fn add_edge(&mut self, task_gid: &str, dependency_gid: &str) {
+        let edge = Dependency {
+            task_gid: task_gid.to_string(),
+            dependency_gid: dependency_gid.to_string(),
+        };
+        if self.edges_set.insert(edge.clone()) {
+            self.adjacency
+                .entry(edge.task_gid.clone())
+                .or_default()
+                .push(edge.dependency_gid.clone());
+            self.edges.push(edge);
+        }
+    }
+
+    /// Replace tasks by other tasks in all dependencies, dropping dependencies of tasks on themselves.
+    pub fn merge_tasks(&mut self, replacements: &HashMap<String, String>) {
+        let edges = std::mem::take(&mut self.edges);
+        self.edges_set.clear();
+        self.adjacency.clear();
+        for edge in edges {
+            let task_gid = replacements.get(&edge.task_gid).unwrap_or(&edge.task_gid);
+            let dependency_gid = replacements
+                .get(&edge.dependency_gid)
+                .unwrap_or(&edge.dependency_gid);
+            if task_gid != dependency_gid {
+                self.add_edge(task_gid, dependency_gid);
+            }
+        }
+    }
+
+    /// Get all dependencies in order of addition.
+    pub fn edges(&self) -> &[Dependency] {
+        &self.edges
+    }
+
+    /// Get gids of tasks the given task depends on.
+    pub fn get_dependencies(&self, task_gid: &str) -> Vec<&str> {
+        self.adjacency
+            .get(task_gid)
+            .map(|dependencies| dependencies.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// Get dependencies with at least one end missing from export.
+    pub fn get_dangling(&self) -> Vec<&Dependency> {
+        self.edges
+            .iter()
+            .filter(|edge| {
+                !self.gids.contains(&edge.task_gid) || !self.gids.contains(&edge.dependency_gid)
+            })
+            .collect()
+    }
+
+    /// Get creation time of placeholder of task missing from export: the earliest creation time
+    /// of tasks it is linked with by dependencies, so that it is the same on each conversion.
+    pub fn get_placeholder_entry(&self, gid: &str) -> Option<DateTime<Utc>> {
+        self.edges
+            .iter()
+            .filter_map(|edge| {
+                if edge.task_gid == gid {
+                    self.entries.get(&edge.dependency_gid)
+                } else if edge.dependency_gid == gid {
+                    self.entries.get(&edge.task_gid)
+                } else {
+                    None
+                }
+            })
+            .min()
+            .copied()
+    }
+
+    /// Remove dependency by its index in order of addition.
+    pub fn remove_edge(&mut self, index: usize) -> Dependency {
+        let edge = self.edges.remove(index);
+        self.edges_set.remove(&edge);
+        if let Some(dependencies) = self.adjacency.get_mut(&edge.task_gid) {
+            dependencies.retain(|dependency_gid| *dependency_gid != edge.dependency_gid);
+        }
+        edge
+    }
+
+    /// Find dependency cycle, return indices of its dependencies in order of path.
+    pub fn find_cycle(&self) -> Option<Vec<usize>> {
+        let mut outgoing: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, edge) in self.edges.iter().enumerate() {
+            outgoing
+                .entry(edge.task_gid.as_str())
+                .or_default()
+                .push(index);
+        }
+
+        // Iterative depth-first search, tasks on current path are `InProgress`
+        #[derive(PartialEq)]
+        enum State {
+            InProgress,
+            Done,
+        }
+        let mut states: HashMap<&str, State> = HashMap::new();
+
+        for start in self.edges.iter().map(|edge| edge.task_gid.as_str()) {
+            if states.contains_key(start) {
+                continue;
+            }
+            states.insert(start, State::InProgress);
+            let mut stack: Vec<(&str, usize)> = vec![(start, 0)];
+            let mut path_edges: Vec<usize> = Vec::new();
+
+            while let Some((gid, next)) = stack.last_mut() {
+                let edge_indices = outgoing.get(*gid).map(Vec::as_slice).unwrap_or(&[]);
+                if let Some(&edge_index) = edge_indices.get(*next) {
+                    *next += 1;
+                    let target = self.edges[edge_index].dependency_gid.as_str();
+                    match states.get(target) {
+                        Some(State::InProgress) => {
+                            let position = stack.iter().position(|(gid, _)| *gid == target)?;
+                            let mut cycle = path_edges[position..].to_vec();
+                            cycle.push(edge_index);
+                            return Some(cycle);
+                        }
+                        Some(State::Done) => {}
+                        None => {
+                            states.insert(target, State::InProgress);
+                            stack.push((target, 0));
+                            path_edges.push(edge_index);
+                        }
+                    }
+                } else {
+                    states.insert(gid, State::Done);
+                    stack.pop();
+                    path_edges.pop();
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Get cycle as list of task names and gids, starting and ending with the same task.
+    pub fn describe_cycle(&self, cycle: &[usize]) -> Vec<String> {
+        let mut gids: Vec<&str> = cycle
+            .iter()
+            .map(|&index| self.edges[index].task_gid.as_str())
+            .collect();
+        if let Some(&first) = gids.first() {
+            gids.push(first);
+        }
+        gids.into_iter()
+            .map(|gid| match self.names.get(gid) {
+                Some(name) => format!("{} ({})", name, gid),
+                None => gid.to_string(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,411 @@
+//! Comparison of two Asana exports
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    asana,
+    convert::{convert_tasks_with_uuids, ConversionReport, ConvertError, ConvertOptions},
+    hierarchy::rebuild_hierarchy,
+    taskwarrior::{self, Priority},
+};
+
+/// Kind of change of single Asana task between two exports.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum TaskChangeKind {
+    #[serde(rename = "added")]
+    Added,
+    #[serde(rename = "removed")]
+    Removed,
+    #[serde(rename = "completed")]
+    Completed,
+    #[serde(rename = "reopened")]
+    Reopened,
+    #[serde(rename = "renamed")]
+    Renamed,
+    #[serde(rename = "moved_section")]
+    MovedSection,
+    #[serde(rename = "reprioritized")]
+    Reprioritized,
+    #[serde(rename = "redated")]
+    Redated,
+}
+
+impl Display for TaskChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TaskChangeKind::Added => "added",
+            TaskChangeKind::Removed => "removed",
+            TaskChangeKind::Completed => "completed",
+            TaskChangeKind::Reopened => "reopened",
+            TaskChangeKind::Renamed => "renamed",
+            TaskChangeKind::MovedSection => "moved section",
+            TaskChangeKind::Reprioritized => "reprioritized",
+            TaskChangeKind::Redated => "redated",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Change of single Asana task between two exports.
+#[derive(Serialize, Clone, Debug)]
+pub struct TaskChange {
+    pub kind: TaskChangeKind,
+    pub gid: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<String>,
+}
+
+/// Kind of change of converted Taskwarrior task.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum TaskwarriorChangeKind {
+    /// Task will be created by import.
+    #[serde(rename = "created")]
+    Created,
+    /// Task will be updated by import.
+    #[serde(rename = "modified")]
+    Modified,
+    /// Task is not present in new output, import will leave it as is.
+    #[serde(rename = "orphaned")]
+    Orphaned,
+}
+
+impl Display for TaskwarriorChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TaskwarriorChangeKind::Created => "created",
+            TaskwarriorChangeKind::Modified => "modified",
+            TaskwarriorChangeKind::Orphaned => "orphaned",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Change of converted Taskwarrior task.
+#[derive(Serialize, Clone, Debug)]
+pub struct TaskwarriorChange {
+    pub kind: TaskwarriorChangeKind,
+    pub uuid: taskwarrior::UUID,
+    pub description: String,
+    pub fields: Vec<String>,
+}
+
+/// Difference between two Asana exports.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ExportsDiff {
+    pub asana: Vec<TaskChange>,
+    pub taskwarrior: Vec<TaskwarriorChange>,
+}
+
+/// Collect Asana tasks and their subtasks in export order, with their priorities.
+///
+/// Priority is found the way converter does it: by section of top-level task, which its subtasks
+/// inherit.
+fn flatten_tasks<'a>(
+    tasks: &'a [asana::Task],
+    parent_priority: Option<&Option<Priority>>,
+    options: &ConvertOptions,
+    output: &mut Vec<(&'a asana::Task, Option<Priority>)>,
+) {
+    for task in tasks {
+        let priority = match parent_priority {
+            Some(parent_priority) => parent_priority.clone(),
+            None => task
+                .memberships
+                .first()
+                .and_then(|membership| options.get_section_priority(&membership.section)),
+        };
+        output.push((task, priority.clone()));
+        if let Some(subtasks) = &task.subtasks {
+            flatten_tasks(subtasks, Some(&priority), options, output);
+        }
+    }
+}
+
+fn get_section(task: &asana::Task) -> Option<String> {
+    task.memberships
+        .first()
+        .map(|membership| membership.project.name.clone() + ": " + &membership.section.name)
+}
+
+fn get_priority_name(priority: &Option<Priority>) -> Option<String> {
+    priority.as_ref().map(|priority| match priority {
+        Priority::High => "H".to_string(),
+        Priority::Medium => "M".to_string(),
+        Priority::Low => "L".to_string(),
+    })
+}
+
+fn get_dates(task: &asana::Task) -> Vec<(&'static str, Option<String>)> {
+    vec![
+        ("due_on", task.due_on.map(|date| date.to_string())),
+        ("due_at", task.due_at.map(|date| date.to_rfc3339())),
+        ("start_on", task.start_on.map(|date| date.to_rfc3339())),
+    ]
+}
+
+fn compare_tasks(
+    (old_task, old_priority): &(&asana::Task, Option<Priority>),
+    (new_task, new_priority): &(&asana::Task, Option<Priority>),
+    changes: &mut Vec<TaskChange>,
+) {
+    let change = |kind: TaskChangeKind,
+                  field: Option<&str>,
+                  old: Option<String>,
+                  new: Option<String>| TaskChange {
+        kind,
+        gid: new_task.gid.clone(),
+        name: new_task.name.clone(),
+        field: field.map(str::to_string),
+        old,
+        new,
+    };
+
+    if !old_task.completed && new_task.completed {
+        changes.push(change(
+            TaskChangeKind::Completed,
+            None,
+            None,
+            new_task.completed_at.map(|date| date.to_rfc3339()),
+        ));
+    } else if old_task.completed && !new_task.completed {
+        changes.push(change(TaskChangeKind::Reopened, None, None, None));
+    }
+
+    if old_task.name != new_task.name {
+        changes.push(change(
+            TaskChangeKind::Renamed,
+            None,
+            Some(old_task.name.clone()),
+            Some(new_task.name.clone()),
+        ));
+    }
+
+    let (old_section, new_section) = (get_section(old_task), get_section(new_task));
+    if old_section != new_section {
+        changes.push(change(
+            TaskChangeKind::MovedSection,
+            None,
+            old_section,
+            new_section,
+        ));
+    }
+
+    let (old_priority, new_priority) = (
+        get_priority_name(old_priority),
+        get_priority_name(new_priority),
+    );
+    if old_priority != new_priority {
+        changes.push(change(
+            TaskChangeKind::Reprioritized,
+            None,
+            old_priority,
+            new_priority,
+        ));
+    }
+
+    for ((field, old_date), (_, new_date)) in
+        get_dates(old_task).into_iter().zip(get_dates(new_task))
+    {
+        if old_date != new_date {
+            changes.push(change(
+                TaskChangeKind::Redated,
+                Some(field),
+                old_date,
+                new_date,
+            ));
+        }
+    }
+}
+
+/// Compare Asana tasks of two exports by their gid.
+///
+/// Subtask trees of both exports are expected to be rebuilt with `rebuild_hierarchy`, so that
+/// subtasks get priority of their top-level tasks.
+pub fn diff_asana_tasks(
+    old_tasks: &[asana::Task],
+    new_tasks: &[asana::Task],
+    options: &ConvertOptions,
+) -> Vec<TaskChange> {
+    let mut old_flat = Vec::new();
+    flatten_tasks(old_tasks, None, options, &mut old_flat);
+    let mut new_flat = Vec::new();
+    flatten_tasks(new_tasks, None, options, &mut new_flat);
+
+    let old_by_gid: HashMap<&str, &(&asana::Task, Option<Priority>)> = old_flat
+        .iter()
+        .map(|old| (old.0.gid.as_str(), old))
+        .collect();
+    let new_gids: HashSet<&str> = new_flat.iter().map(|(task, _)| task.gid.as_str()).collect();
+
+    let mut changes = Vec::new();
+    for new in &new_flat {
+        let new_task = new.0;
+        match old_by_gid.get(new_task.gid.as_str()) {
+            Some(old) => compare_tasks(old, new, &mut changes),
+            None => changes.push(TaskChange {
+                kind: TaskChangeKind::Added,
+                gid: new_task.gid.clone(),
+                name: new_task.name.clone(),
+                field: None,
+                old: None,
+                new: None,
+            }),
+        }
+    }
+    for (old_task, _) in &old_flat {
+        if !new_gids.contains(old_task.gid.as_str()) {
+            changes.push(TaskChange {
+                kind: TaskChangeKind::Removed,
+                gid: old_task.gid.clone(),
+                name: old_task.name.clone(),
+                field: None,
+                old: None,
+                new: None,
+            });
+        }
+    }
+
+    changes
+}
+
+fn get_task_fields(task: &taskwarrior::Task) -> serde_json::Map<String, Value> {
+    match serde_json::to_value(task) {
+        Ok(Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// Compare converted Taskwarrior tasks by their UUID.
+pub fn diff_taskwarrior_tasks(
+    old_tasks: &[taskwarrior::Task],
+    new_tasks: &[taskwarrior::Task],
+) -> Vec<TaskwarriorChange> {
+    let old_by_uuid: HashMap<Uuid, &taskwarrior::Task> = old_tasks
+        .iter()
+        .map(|task| (task.uuid.uuid, task))
+        .collect();
+    let new_uuids: HashSet<Uuid> = new_tasks.iter().map(|task| task.uuid.uuid).collect();
+
+    let mut changes = Vec::new();
+    for new_task in new_tasks {
+        match old_by_uuid.get(&new_task.uuid.uuid) {
+            Some(old_task) => {
+                let old_fields = get_task_fields(old_task);
+                let new_fields = get_task_fields(new_task);
+                let mut keys: Vec<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+                keys.sort();
+                keys.dedup();
+                let fields: Vec<String> = keys
+                    .into_iter()
+                    .filter(|&key| old_fields.get(key) != new_fields.get(key))
+                    .cloned()
+                    .collect();
+                if !fields.is_empty() {
+                    changes.push(TaskwarriorChange {
+                        kind: TaskwarriorChangeKind::Modified,
+                        uuid: new_task.uuid.clone(),
+                        description: new_task.description.clone(),
+                        fields,
+                    });
+                }
+            }
+            None => changes.push(TaskwarriorChange {
+                kind: TaskwarriorChangeKind::Created,
+                uuid: new_task.uuid.clone(),
+                description: new_task.description.clone(),
+                fields: Vec::new(),
+            }),
+        }
+    }
+    for old_task in old_tasks {
+        if !new_uuids.contains(&old_task.uuid.uuid) {
+            changes.push(TaskwarriorChange {
+                kind: TaskwarriorChangeKind::Orphaned,
+                uuid: old_task.uuid.clone(),
+                description: old_task.description.clone(),
+                fields: Vec::new(),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Compare two Asana exports, both as is and after conversion to Taskwarrior.
+pub fn diff_exports(
+    old_tasks: Vec<asana::Task>,
+    new_tasks: Vec<asana::Task>,
+    options: &ConvertOptions,
+) -> Result<ExportsDiff, ConvertError> {
+    // Both exports are compared as converter sees them
+    let old_tasks = rebuild_hierarchy(old_tasks, &mut ConversionReport::default());
+    let new_tasks = rebuild_hierarchy(new_tasks, &mut ConversionReport::default());
+    let asana_changes = diff_asana_tasks(&old_tasks, &new_tasks, options);
+
+    // Shared UUID mapping makes the same Asana task get the same UUID in both outputs
+    let mut uuids = HashMap::new();
+    let mut report = ConversionReport::default();
+    let old_output = convert_tasks_with_uuids(old_tasks, &mut uuids, options, &mut report)?;
+    let new_output = convert_tasks_with_uuids(new_tasks, &mut uuids, options, &mut report)?;
+
+    Ok(ExportsDiff {
+        asana: asana_changes,
+        taskwarrior: diff_taskwarrior_tasks(&old_output, &new_output),
+    })
+}
+
+impl Display for ExportsDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Asana changes:")?;
+        writeln!(f, "{:<14} {:<18} {:<40} DETAILS", "CHANGE", "GID", "TASK")?;
+        for change in &self.asana {
+            let mut details = String::new();
+            if let Some(field) = &change.field {
+                details += field;
+                details += ": ";
+            }
+            if change.old.is_some() || change.new.is_some() {
+                details += &format!(
+                    "{} -> {}",
+                    change.old.as_deref().unwrap_or("-"),
+                    change.new.as_deref().unwrap_or("-")
+                );
+            }
+            writeln!(
+                f,
+                "{:<14} {:<18} {:<40} {}",
+                change.kind.to_string(),
+                change.gid,
+                change.name,
+                details
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Taskwarrior changes:")?;
+        writeln!(f, "{:<10} {:<36} {:<40} FIELDS", "CHANGE", "UUID", "TASK")?;
+        for change in &self.taskwarrior {
+            writeln!(
+                f,
+                "{:<10} {:<36} {:<40} {}",
+                change.kind.to_string(),
+                change.uuid.to_string(),
+                change.description,
+                change.fields.join(", ")
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Rebuilding subtask tree of flat lists of Asana tasks
+use std::collections::{HashMap, HashSet};
+
+use crate::{asana, convert::ConversionReport};
+
+/// Move tasks into `subtasks` of tasks referred by their `parent` field.
+///
+/// Tasks whose parent is missing from the list, or whose parents form a cycle, stay top-level.
+/// Order of tasks is kept, both for top-level tasks and for subtasks of each task.
+pub fn nest_subtasks(tasks: Vec<asana::Task>) -> Vec<asana::Task> {
+    let mut indices: HashMap<&str, usize> = HashMap::new();
+    for (index, task) in tasks.iter().enumerate() {
+        indices.entry(&task.gid).or_insert(index);
+    }
+    let parents: Vec<Option<usize>> = tasks
+        .iter()
+        .enumerate()
+        .map(|(index, task)| {
+            task.parent
+                .as_ref()
+                .and_then(|parent| indices.get(parent.gid.as_str()))
+                .copied()
+                .filter(|&parent_index| parent_index != index)
+        })
+        .collect();
+    nest_subtasks_by_index(tasks, &parents)
+}
+
+/// Move tasks into `subtasks` of their parents, given index of parent of each task.
+pub fn nest_subtasks_by_index(
+    tasks: Vec<asana::Task>,
+    parents: &[Option<usize>],
+) -> Vec<asana::Task> {
+    // Attach children to parents, deepest first, so that each task is moved with its subtasks
+    let mut depths: Vec<usize> = vec![0; tasks.len()];
+    for (index, depth) in depths.iter_mut().enumerate() {
+        let mut current = index;
+        while let Some(parent_index) = parents[current] {
+            *depth += 1;
+            current = parent_index;
+            if *depth > parents.len() {
+                // Cycle of parent references, treat task as top-level
+                *depth = 0;
+                break;
+            }
+        }
+    }
+    let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (index, parent) in parents.iter().enumerate() {
+        if let (Some(parent_index), true) = (parent, depths[index] > 0) {
+            children.entry(*parent_index).or_default().push(index);
+        }
+    }
+
+    let mut tasks: Vec<Option<asana::Task>> = tasks.into_iter().map(Some).collect();
+    let mut parent_indices: Vec<usize> = children.keys().copied().collect();
+    parent_indices.sort_by_key(|&index| std::cmp::Reverse(depths[index]));
+    for parent_index in parent_indices {
+        let mut subtasks: Vec<asana::Task> = children[&parent_index]
+            .iter()
+            .filter_map(|&index| tasks[index].take())
+            .collect();
+        if let Some(parent_task) = tasks[parent_index].as_mut() {
+            let parent_subtasks = parent_task.subtasks.get_or_insert_with(Vec::new);
+            parent_subtasks.append(&mut subtasks);
+            parent_task.num_subtasks = Some(parent_subtasks.len() as u64);
+        }
+    }
+
+    tasks.into_iter().flatten().collect()
+}
+
+/// Collect gids of all subtasks of tasks, recursively.
+pub fn collect_subtask_gids<'a>(tasks: &'a [asana::Task], gids: &mut HashSet<&'a str>) {
+    for task in tasks {
+        if let Some(subtasks) = &task.subtasks {
+            for subtask in subtasks {
+                gids.insert(&subtask.gid);
+            }
+            collect_subtask_gids(subtasks, gids);
+        }
+    }
+}
+
+/// Collect gids of tasks and all their subtasks, recursively.
+fn collect_gids(tasks: &[asana::Task], gids: &mut HashSet<String>) {
+    for task in tasks {
+        gids.insert(task.gid.clone());
+        if let Some(subtasks) = &task.subtasks {
+            collect_gids(subtasks, gids);
+        }
+    }
+}
+
+/// Append tasks to `subtasks` of their parents found anywhere in trees of `tasks`.
+fn attach_to_nested_parents(
+    tasks: &mut [asana::Task],
+    children: &mut HashMap<String, Vec<asana::Task>>,
+) {
+    for task in tasks {
+        if let Some(mut task_children) = children.remove(&task.gid) {
+            let subtasks = task.subtasks.get_or_insert_with(Vec::new);
+            subtasks.append(&mut task_children);
+            task.num_subtasks = Some(subtasks.len() as u64);
+        }
+        if let Some(subtasks) = &mut task.subtasks {
+            attach_to_nested_parents(subtasks, children);
+        }
+    }
+}
+
+/// Fill fields missing from `task` with ones of `copy`, another export of the same task.
+///
+/// Nested copies are often trimmed, while top-level copies are full records, or vice versa.
+fn merge_task_copy(task: &mut asana::Task, copy: asana::Task) {
+    fn merge_string(value: &mut String, copy: String) {
+        if value.is_empty() {
+            *value = copy;
+        }
+    }
+    fn merge_vec<T>(value: &mut Vec<T>, copy: Vec<T>) {
+        if value.is_empty() {
+            *value = copy;
+        }
+    }
+    fn merge_option<T>(value: &mut Option<T>, copy: Option<T>) {
+        if value.is_none() {
+            *value = copy;
+        }
+    }
+
+    merge_string(&mut task.resource_type, copy.resource_type);
+    merge_string(&mut task.name, copy.name);
+    merge_option(&mut task.approval_status, copy.approval_status);
+    merge_string(&mut task.assignee_status, copy.assignee_status);
+    task.completed |= copy.completed;
+    merge_option(&mut task.completed_at, copy.completed_at);
+    merge_option(&mut task.completed_by, copy.completed_by);
+    task.created_at = task.created_at.min(copy.created_at);
+    merge_option(&mut task.dependencies, copy.dependencies);
+    merge_option(&mut task.dependents, copy.dependents);
+    merge_option(&mut task.due_at, copy.due_at);
+    merge_option(&mut task.due_on, copy.due_on);
+    merge_option(&mut task.external, copy.external);
+    merge_option(&mut task.html_notes, copy.html_notes);
+    merge_option(
+        &mut task.is_rendered_as_separator,
+        copy.is_rendered_as_separator,
+    );
+    task.liked |= copy.liked;
+    merge_vec(&mut task.likes, copy.likes);
+    merge_vec(&mut task.projects, copy.projects);
+    merge_vec(&mut task.memberships, copy.memberships);
+    task.modified_at = task.modified_at.max(copy.modified_at);
+    merge_string(&mut task.notes, copy.notes);
+    task.num_likes = task.num_likes.max(copy.num_likes);
+    merge_string(&mut task.resource_subtype, copy.resource_subtype);
+    merge_option(&mut task.start_on, copy.start_on);
+    merge_option(&mut task.assignee, copy.assignee);
+    merge_option(&mut task.assignee_section, copy.assignee_section);
+    merge_option(&mut task.custom_fields, copy.custom_fields);
+    merge_vec(&mut task.followers, copy.followers);
+    merge_option(&mut task.parent, copy.parent);
+    merge_vec(&mut task.tags, copy.tags);
+    if task.workspace.gid.is_empty() {
+        task.workspace = copy.workspace;
+    }
+    if let Some(copy_subtasks) = copy.subtasks {
+        let subtasks = task.subtasks.get_or_insert_with(Vec::new);
+        let known_gids: HashSet<String> = subtasks.iter().map(|task| task.gid.clone()).collect();
+        subtasks.extend(
+            copy_subtasks
+                .into_iter()
+                .filter(|subtask| !known_gids.contains(&subtask.gid)),
+        );
+        task.num_subtasks = Some(subtasks.len() as u64);
+    }
+    merge_option(&mut task.num_subtasks, copy.num_subtasks);
+}
+
+/// Merge dropped copies of tasks into the kept ones, found anywhere in trees of `tasks`.
+fn merge_task_copies(tasks: &mut [asana::Task], copies: &mut HashMap<String, Vec<asana::Task>>) {
+    for task in tasks {
+        for copy in copies.remove(&task.gid).into_iter().flatten() {
+            merge_task_copy(task, copy);
+        }
+        if let Some(subtasks) = &mut task.subtasks {
+            merge_task_copies(subtasks, copies);
+        }
+    }
+}
+
+/// Rebuild subtask tree of tasks exported both nested and as separate top-level entries.
+///
+/// Top-level tasks that also appear nested (or more than once) are merged into the nested (or
+/// first) copy, other top-level tasks with `parent` are moved to their parents, wherever they are
+/// in the tree. Tasks whose parents are missing from export stay top-level and are reported.
+pub fn rebuild_hierarchy(
+    tasks: Vec<asana::Task>,
+    report: &mut ConversionReport,
+) -> Vec<asana::Task> {
+    let nested_gids: HashSet<String> = {
+        let mut nested_gids = HashSet::new();
+        collect_subtask_gids(&tasks, &mut nested_gids);
+        nested_gids.into_iter().map(str::to_string).collect()
+    };
+    let mut top_level_gids = HashSet::new();
+    let mut copies: HashMap<String, Vec<asana::Task>> = HashMap::new();
+    let mut tasks: Vec<asana::Task> = tasks
+        .into_iter()
+        .filter_map(|task| {
+            if !nested_gids.contains(&task.gid) && top_level_gids.insert(task.gid.clone()) {
+                Some(task)
+            } else {
+                report.duplicate_tasks += 1;
+                copies.entry(task.gid.clone()).or_default().push(task);
+                None
+            }
+        })
+        .collect();
+    merge_task_copies(&mut tasks, &mut copies);
+
+    let mut tasks = nest_subtasks(tasks);
+
+    let mut all_gids = HashSet::new();
+    collect_gids(&tasks, &mut all_gids);
+    let mut children: HashMap<String, Vec<asana::Task>> = HashMap::new();
+    let mut top_level_tasks = Vec::new();
+    for task in tasks.drain(..) {
+        match task.parent.as_ref().map(|parent| parent.gid.clone()) {
+            Some(parent_gid) if all_gids.contains(&parent_gid) && parent_gid != task.gid => {
+                children.entry(parent_gid).or_default().push(task);
+            }
+            Some(parent_gid) if !parent_gid.is_empty() => {
+                report.missing_parents.push((task.gid.clone(), parent_gid));
+                top_level_tasks.push(task);
+            }
+            _ => top_level_tasks.push(task),
+        }
+    }
+    // Each pass reaches parents inside trees of tasks moved on previous pass
+    let mut remaining = usize::MAX;
+    while !children.is_empty() && children.len() < remaining {
+        remaining = children.len();
+        attach_to_nested_parents(&mut top_level_tasks, &mut children);
+    }
+    // Tasks whose parents form a cycle stay top-level
+    for (_, mut remaining_children) in children.drain() {
+        top_level_tasks.append(&mut remaining_children);
+    }
+
+    top_level_tasks
+}
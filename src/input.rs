@@ -0,0 +1,757 @@
+//! Reading Asana tasks from input files of supported formats
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess,
+    Visitor,
+};
+use serde_json::Value;
+
+use crate::{asana, asana_csv, asana_org::OrganizationExport};
+
+/// Format of input file with Asana tasks.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Detect format by file extension, JSON by default.
+    #[default]
+    Auto,
+    /// Asana API JSON export: single response, stream or array of response pages, or directory of
+    /// page files.
+    Json,
+    /// Asana web UI CSV export.
+    Csv,
+    /// Asana organization export: file with collections of users, teams, projects, sections and
+    /// tasks, or directory of collection files (`users.json`, `tasks.json` and so on).
+    Organization,
+}
+
+impl InputFormat {
+    /// Resolve `Auto` format by file extension, ignoring compression extension.
+    pub fn detect(self, path: &Path) -> Self {
+        match self {
+            InputFormat::Auto => match get_content_extension(path) {
+                Some(extension) if extension.eq_ignore_ascii_case("csv") => InputFormat::Csv,
+                _ => InputFormat::Json,
+            },
+            format => format,
+        }
+    }
+}
+
+/// Path that means standard input or output instead of file.
+pub const STDIO_PATH: &str = "-";
+
+/// Check if path means standard input or output.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
+/// Compression of input file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    /// ZIP archive, its files are read one after another.
+    Zip,
+}
+
+impl Compression {
+    /// Detect compression by file extension.
+    fn detect(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gz") => Compression::Gzip,
+            Some(extension) if extension.eq_ignore_ascii_case("zst") => Compression::Zstd,
+            Some(extension) if extension.eq_ignore_ascii_case("zip") => Compression::Zip,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Get name of file without extensions of content and compression, for example, `tasks` for
+/// `tasks.csv.gz`.
+fn get_content_stem(path: &Path) -> Option<&str> {
+    let path = match Compression::detect(path) {
+        Compression::None => path,
+        _ => Path::new(path.file_stem()?),
+    };
+    path.file_stem().and_then(|stem| stem.to_str())
+}
+
+/// Get extension of file content, for example, `csv` for `tasks.csv.gz`.
+fn get_content_extension(path: &Path) -> Option<&str> {
+    let path = match Compression::detect(path) {
+        Compression::None => path,
+        _ => Path::new(path.file_stem()?),
+    };
+    path.extension().and_then(|extension| extension.to_str())
+}
+
+/// Reader of all files of ZIP archive, one after another in natural order of file names.
+///
+/// Entries of archive borrow it, so each file is decompressed into memory when it is reached:
+/// memory use is bounded by the largest file of archive rather than the whole archive.
+struct ZipArchiveReader<R: Read + io::Seek> {
+    archive: zip::ZipArchive<R>,
+    /// Names of files that are not read yet, in reverse order.
+    remaining_names: Vec<String>,
+    current_file: Cursor<Vec<u8>>,
+}
+
+impl<R: Read + io::Seek> ZipArchiveReader<R> {
+    fn new(reader: R) -> Result<Self, InputError> {
+        let archive = zip::ZipArchive::new(reader)?;
+        let mut remaining_names: Vec<String> = archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(str::to_string)
+            .collect();
+        remaining_names.sort_by_key(|name| get_natural_sort_key(Path::new(name)));
+        remaining_names.reverse();
+        Ok(ZipArchiveReader {
+            archive,
+            remaining_names,
+            current_file: Cursor::new(Vec::new()),
+        })
+    }
+}
+
+impl<R: Read + io::Seek> Read for ZipArchiveReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let length = self.current_file.read(buf)?;
+            if length > 0 || buf.is_empty() {
+                return Ok(length);
+            }
+            let name = match self.remaining_names.pop() {
+                Some(name) => name,
+                None => return Ok(0),
+            };
+            let mut contents = Vec::new();
+            self.archive
+                .by_name(&name)
+                .map_err(io::Error::other)?
+                .read_to_end(&mut contents)?;
+            self.current_file = Cursor::new(contents);
+        }
+    }
+}
+
+/// Open input file (or standard input for `-`), decompressing it according to its extension.
+pub fn open_input(path: &Path) -> Result<Box<dyn Read>, InputError> {
+    if is_stdio(path) {
+        return Ok(Box::new(io::stdin()));
+    }
+    let file = File::open(path)?;
+    Ok(match Compression::detect(path) {
+        Compression::None => Box::new(BufReader::new(file)),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(BufReader::new(file))),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        Compression::Zip => Box::new(ZipArchiveReader::new(BufReader::new(file))?),
+    })
+}
+
+/// Error of reading input file.
+#[derive(Debug)]
+pub enum InputError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Zip(zip::result::ZipError),
+}
+
+impl Display for InputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputError::Io(err) => write!(f, "Failed to read input: {}", err),
+            InputError::Json(err) => write!(f, "Failed to parse input JSON: {}", err),
+            InputError::Csv(err) => write!(f, "Failed to parse input CSV: {}", err),
+            InputError::Zip(err) => write!(f, "Failed to read input archive: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for InputError {
+    fn from(err: io::Error) -> Self {
+        InputError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for InputError {
+    fn from(err: serde_json::Error) -> Self {
+        InputError::Json(err)
+    }
+}
+
+impl From<csv::Error> for InputError {
+    fn from(err: csv::Error) -> Self {
+        InputError::Csv(err)
+    }
+}
+
+impl From<zip::result::ZipError> for InputError {
+    fn from(err: zip::result::ZipError) -> Self {
+        InputError::Zip(err)
+    }
+}
+
+/// Notes about input that user should know about.
+#[derive(Clone, Debug, Default)]
+pub struct InputReport {
+    /// Problems with chain of pages linked by `next_page`.
+    pub page_chain_warnings: Vec<String>,
+    /// Number of tasks found on more than one page.
+    pub duplicate_tasks: usize,
+    /// Task fields missing from input and set to default values, with number of tasks.
+    pub missing_fields: BTreeMap<String, usize>,
+    /// Number of references to resources missing from organization export.
+    pub unresolved_references: usize,
+}
+
+impl Display for InputReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for warning in &self.page_chain_warnings {
+            writeln!(f, "Warning: {}", warning)?;
+        }
+        if self.duplicate_tasks > 0 {
+            writeln!(
+                f,
+                "{} tasks found on more than one page, only first occurrence is used",
+                self.duplicate_tasks
+            )?;
+        }
+        if !self.missing_fields.is_empty() {
+            writeln!(
+                f,
+                "Task fields missing from input and set to default values:"
+            )?;
+            for (field, count) in &self.missing_fields {
+                writeln!(f, "  {}: {} tasks", field, count)?;
+            }
+        }
+        if self.unresolved_references > 0 {
+            writeln!(
+                f,
+                "{} references to resources missing from organization export",
+                self.unresolved_references
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Count fields of task JSON objects (and their subtasks) that are missing from `expected_fields`.
+fn count_missing_fields(
+    tasks: &[Value],
+    expected_fields: &[String],
+    missing_fields: &mut BTreeMap<String, usize>,
+) {
+    for task in tasks {
+        if let Value::Object(task) = task {
+            for field in expected_fields {
+                if !task.contains_key(field) {
+                    *missing_fields.entry(field.clone()).or_default() += 1;
+                }
+            }
+            if let Some(Value::Array(subtasks)) = task.get("subtasks") {
+                count_missing_fields(subtasks, expected_fields, missing_fields);
+            }
+        }
+    }
+}
+
+/// Get task fields that should be present in full export.
+fn get_expected_fields() -> Result<Vec<String>, InputError> {
+    // Fields that are always serialized are the ones that were mandatory in full export,
+    // optional fields serialized as null are legitimately absent
+    match serde_json::to_value(asana::Task::default())? {
+        Value::Object(fields) => Ok(fields
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(name, _)| name)
+            .collect()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Parse page from JSON value, counting missing task fields.
+fn parse_page(value: Value, report: &mut InputReport) -> Result<asana::Page, InputError> {
+    let expected_fields = get_expected_fields()?;
+    if let Some(Value::Array(tasks)) = value.get("data") {
+        count_missing_fields(tasks, &expected_fields, &mut report.missing_fields);
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Read pages from JSON stream, each value is either single page or array of pages.
+pub fn read_pages<R: Read>(
+    reader: R,
+    pages: &mut Vec<asana::Page>,
+    report: &mut InputReport,
+) -> Result<(), InputError> {
+    for value in serde_json::Deserializer::from_reader(reader).into_iter::<Value>() {
+        match value? {
+            Value::Array(values) => {
+                for value in values {
+                    pages.push(parse_page(value, report)?);
+                }
+            }
+            value => pages.push(parse_page(value, report)?),
+        }
+    }
+    Ok(())
+}
+
+/// Split file name into text and number chunks, so that `page_10` comes after `page_2`.
+fn get_natural_sort_key(path: &Path) -> Vec<(String, u64)> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    name.chars()
+        .collect::<Vec<char>>()
+        .chunk_by(|a, b| a.is_ascii_digit() == b.is_ascii_digit())
+        .map(|chunk| {
+            let chunk: String = chunk.iter().collect();
+            match chunk.parse::<u64>() {
+                Ok(number) => (String::new(), number),
+                Err(_) => (chunk, 0),
+            }
+        })
+        .collect()
+}
+
+/// Get paths of all (possibly compressed) JSON files of directory, in natural order of file names.
+fn get_page_file_paths(path: &Path) -> Result<Vec<PathBuf>, InputError> {
+    let mut file_paths: Vec<PathBuf> = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    file_paths.retain(|file_path| {
+        file_path.is_file()
+            && get_content_extension(file_path)
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+    });
+    file_paths.sort_by_key(|file_path| get_natural_sort_key(file_path));
+    Ok(file_paths)
+}
+
+/// Read pages from all JSON files of directory, in natural order of file names.
+fn read_pages_directory(
+    path: &Path,
+    pages: &mut Vec<asana::Page>,
+    report: &mut InputReport,
+) -> Result<(), InputError> {
+    for file_path in get_page_file_paths(path)? {
+        read_pages(open_input(&file_path)?, pages, report)?;
+    }
+    Ok(())
+}
+
+/// Check that pages form unbroken chain, given offsets of next pages referred by each page.
+fn check_page_chain(next_offsets: &[Option<String>], report: &mut InputReport) {
+    let pages_count = next_offsets.len();
+    let mut offsets = HashSet::new();
+
+    for (index, next_offset) in next_offsets.iter().enumerate() {
+        let is_last = index + 1 == pages_count;
+        match (next_offset, is_last) {
+            (Some(next_offset), true) => report.page_chain_warnings.push(format!(
+                "last page refers to next page with offset {}, following pages are missing",
+                next_offset
+            )),
+            (None, false) => report.page_chain_warnings.push(format!(
+                "page {} of {} has no next page, but more pages follow",
+                index + 1,
+                pages_count
+            )),
+            _ => {}
+        }
+        if let Some(next_offset) = next_offset {
+            if !offsets.insert(next_offset) {
+                report.page_chain_warnings.push(format!(
+                    "offset {} of next page is repeated on page {}",
+                    next_offset,
+                    index + 1
+                ));
+            }
+        }
+    }
+}
+
+/// Join tasks of pages by gid, checking that pages form unbroken chain.
+pub fn stitch_pages(pages: Vec<asana::Page>, report: &mut InputReport) -> Vec<asana::Task> {
+    let next_offsets: Vec<Option<String>> = pages
+        .iter()
+        .map(|page| {
+            page.next_page
+                .as_ref()
+                .map(|next_page| next_page.offset.clone())
+        })
+        .collect();
+    check_page_chain(&next_offsets, report);
+
+    let mut gids = HashSet::new();
+    let mut tasks = Vec::new();
+    for page in pages {
+        for task in page.data {
+            if gids.insert(task.gid.clone()) {
+                tasks.push(task);
+            } else {
+                report.duplicate_tasks += 1;
+            }
+        }
+    }
+
+    tasks
+}
+
+/// State of reading pages task by task.
+struct PagesStreamState<'a, F: FnMut(asana::Task)> {
+    callback: F,
+    report: &'a mut InputReport,
+    expected_fields: Vec<String>,
+    gids: HashSet<String>,
+    next_offsets: Vec<Option<String>>,
+}
+
+/// Seed for deserialization of single page or array of pages, passing tasks to callback.
+struct PagesSeed<'a, 'b, F: FnMut(asana::Task)>(&'a mut PagesStreamState<'b, F>);
+
+/// Seed for deserialization of `data` array of page, passing tasks to callback.
+struct TasksSeed<'a, 'b, F: FnMut(asana::Task)>(&'a mut PagesStreamState<'b, F>);
+
+impl<'de, F: FnMut(asana::Task)> DeserializeSeed<'de> for PagesSeed<'_, '_, F> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, F: FnMut(asana::Task)> Visitor<'de> for PagesSeed<'_, '_, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "Asana API response page or array of pages")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while seq.next_element_seed(PagesSeed(&mut *self.0))?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut next_page: Option<asana::NextPage> = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "data" => map.next_value_seed(TasksSeed(&mut *self.0))?,
+                "next_page" => next_page = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        self.0
+            .next_offsets
+            .push(next_page.map(|next_page| next_page.offset));
+        Ok(())
+    }
+}
+
+impl<'de, F: FnMut(asana::Task)> DeserializeSeed<'de> for TasksSeed<'_, '_, F> {
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(asana::Task)> Visitor<'de> for TasksSeed<'_, '_, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "array of Asana tasks")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let state = self.0;
+        while let Some(value) = seq.next_element::<Value>()? {
+            count_missing_fields(
+                std::slice::from_ref(&value),
+                &state.expected_fields,
+                &mut state.report.missing_fields,
+            );
+            let task: asana::Task = serde_json::from_value(value).map_err(de::Error::custom)?;
+            if state.gids.insert(task.gid.clone()) {
+                (state.callback)(task);
+            } else {
+                state.report.duplicate_tasks += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read pages from JSON stream task by task.
+fn stream_pages<R: Read, F: FnMut(asana::Task)>(
+    reader: R,
+    state: &mut PagesStreamState<F>,
+) -> Result<(), InputError> {
+    let mut deserializer = serde_json::Deserializer::from_reader(io::BufReader::new(reader));
+    // Error means that there is something besides whitespace left, that is the next page
+    while deserializer.end().is_err() {
+        PagesSeed(&mut *state).deserialize(&mut deserializer)?;
+    }
+    Ok(())
+}
+
+/// Read Asana tasks from JSON file or directory of pages, passing them to callback one by one
+/// without keeping all of them in memory.
+pub fn for_each_asana_task<F: FnMut(asana::Task)>(
+    path: &Path,
+    report: &mut InputReport,
+    callback: F,
+) -> Result<(), InputError> {
+    let mut state = PagesStreamState {
+        callback,
+        report,
+        expected_fields: get_expected_fields()?,
+        gids: HashSet::new(),
+        next_offsets: Vec::new(),
+    };
+    if path.is_dir() {
+        for file_path in get_page_file_paths(path)? {
+            stream_pages(open_input(&file_path)?, &mut state)?;
+        }
+    } else {
+        stream_pages(open_input(path)?, &mut state)?;
+    }
+    let next_offsets = std::mem::take(&mut state.next_offsets);
+    check_page_chain(&next_offsets, state.report);
+    Ok(())
+}
+
+/// Resource that belongs to task or project, read from sidecar file next to tasks.
+pub trait SidecarResource: DeserializeOwned {
+    fn gid(&self) -> &str;
+    /// Gid of task (or project) that resource belongs to, if it is known from resource itself.
+    fn owner_gid(&self) -> Option<&str>;
+}
+
+impl SidecarResource for asana::Story {
+    fn gid(&self) -> &str {
+        &self.gid
+    }
+
+    fn owner_gid(&self) -> Option<&str> {
+        self.target.as_ref().map(|target| target.gid.as_str())
+    }
+}
+
+impl SidecarResource for asana::Section {
+    fn gid(&self) -> &str {
+        &self.gid
+    }
+
+    fn owner_gid(&self) -> Option<&str> {
+        self.project.as_ref().map(|project| project.gid.as_str())
+    }
+}
+
+impl SidecarResource for asana::Attachment {
+    fn gid(&self) -> &str {
+        &self.gid
+    }
+
+    fn owner_gid(&self) -> Option<&str> {
+        self.parent.as_ref().map(|parent| parent.gid.as_str())
+    }
+}
+
+/// Parse resources from endpoint response page or plain array of resources.
+fn parse_resources<T: DeserializeOwned>(value: Value) -> Result<Vec<T>, InputError> {
+    match value {
+        Value::Object(mut page) if page.contains_key("data") => Ok(serde_json::from_value(
+            page.remove("data").unwrap_or_default(),
+        )?),
+        value => Ok(serde_json::from_value(value)?),
+    }
+}
+
+/// Read resources of tasks (stories, attachments) or projects (sections), keyed by owner gid.
+///
+/// Path may be a directory of endpoint responses named by owner gid (`<gid>.json`), or a single
+/// file with either an object mapping owner gids to their resources, or endpoint responses whose
+/// resources refer to their owner. Order of resources is kept, resources found more than once are
+/// kept only once.
+pub fn read_sidecar_resources<T: SidecarResource>(
+    path: &Path,
+) -> Result<HashMap<String, Vec<T>>, InputError> {
+    let mut resources: HashMap<String, Vec<T>> = HashMap::new();
+
+    if path.is_dir() {
+        for file_path in get_page_file_paths(path)? {
+            let owner_gid = get_content_stem(&file_path).unwrap_or_default().to_string();
+            for value in serde_json::Deserializer::from_reader(open_input(&file_path)?).into_iter()
+            {
+                let mut owner_resources = parse_resources(value?)?;
+                resources
+                    .entry(owner_gid.clone())
+                    .or_default()
+                    .append(&mut owner_resources);
+            }
+        }
+    } else {
+        for value in serde_json::Deserializer::from_reader(open_input(path)?).into_iter() {
+            match value? {
+                Value::Object(object) if !object.contains_key("data") => {
+                    for (owner_gid, value) in object {
+                        let mut owner_resources = parse_resources(value)?;
+                        resources
+                            .entry(owner_gid)
+                            .or_default()
+                            .append(&mut owner_resources);
+                    }
+                }
+                value => {
+                    for resource in parse_resources::<T>(value)? {
+                        if let Some(owner_gid) = resource.owner_gid() {
+                            resources
+                                .entry(owner_gid.to_string())
+                                .or_default()
+                                .push(resource);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for owner_resources in resources.values_mut() {
+        let mut gids = HashSet::new();
+        owner_resources.retain(|resource| gids.insert(resource.gid().to_string()));
+    }
+    Ok(resources)
+}
+
+/// Read stories of tasks, keyed by task gid, see `read_sidecar_resources`.
+///
+/// Stories of each task are sorted by creation time.
+pub fn read_stories(path: &Path) -> Result<HashMap<String, Vec<asana::Story>>, InputError> {
+    let mut stories: HashMap<String, Vec<asana::Story>> = read_sidecar_resources(path)?;
+    for task_stories in stories.values_mut() {
+        task_stories.sort_by_key(|story| story.created_at);
+    }
+    Ok(stories)
+}
+
+/// Read resources from endpoint responses (or arrays of resources) in file or directory of pages.
+pub fn read_resources<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, InputError> {
+    let file_paths = if path.is_dir() {
+        get_page_file_paths(path)?
+    } else {
+        vec![path.to_path_buf()]
+    };
+    let mut resources = Vec::new();
+    for file_path in file_paths {
+        for value in serde_json::Deserializer::from_reader(open_input(&file_path)?).into_iter() {
+            resources.append(&mut parse_resources(value?)?);
+        }
+    }
+    Ok(resources)
+}
+
+/// Read projects from projects endpoint responses, keyed by project gid.
+pub fn read_projects(path: &Path) -> Result<HashMap<String, asana::Project>, InputError> {
+    Ok(read_resources::<asana::Project>(path)?
+        .into_iter()
+        .map(|project| (project.gid.clone(), project))
+        .collect())
+}
+
+/// Get positions of sections in their projects by section gid.
+pub fn get_section_positions(
+    sections: &HashMap<String, Vec<asana::Section>>,
+) -> HashMap<String, usize> {
+    sections
+        .values()
+        .flat_map(|project_sections| {
+            project_sections
+                .iter()
+                .enumerate()
+                .map(|(position, section)| (section.gid.clone(), position))
+        })
+        .collect()
+}
+
+/// Read Asana organization export from file with collections or directory of collection files.
+pub fn read_organization_export(
+    path: &Path,
+    report: &mut InputReport,
+) -> Result<Vec<asana::Task>, InputError> {
+    let mut export = OrganizationExport::default();
+    if path.is_dir() {
+        for file_path in get_page_file_paths(path)? {
+            let collection = get_content_stem(&file_path).unwrap_or_default().to_string();
+            for value in serde_json::Deserializer::from_reader(open_input(&file_path)?).into_iter()
+            {
+                export.add_items(&collection, value?);
+            }
+        }
+    } else {
+        for value in serde_json::Deserializer::from_reader(open_input(path)?).into_iter() {
+            if let Value::Object(collections) = value? {
+                for (collection, items) in collections {
+                    export.add_items(&collection, items);
+                }
+            }
+        }
+    }
+
+    let (tasks, unresolved_references) = export.into_tasks()?;
+    report.unresolved_references += unresolved_references;
+    Ok(tasks)
+}
+
+/// Read Asana tasks from file (or directory of pages) of given format.
+pub fn read_asana_tasks(
+    path: &Path,
+    format: InputFormat,
+    report: &mut InputReport,
+) -> Result<Vec<asana::Task>, InputError> {
+    match format.detect(path) {
+        InputFormat::Csv => Ok(asana_csv::read_tasks(open_input(path)?)?),
+        InputFormat::Organization => read_organization_export(path, report),
+        _ => {
+            let mut pages = Vec::new();
+            if path.is_dir() {
+                read_pages_directory(path, &mut pages, report)?;
+            } else {
+                read_pages(open_input(path)?, &mut pages, report)?;
+            }
+            Ok(stitch_pages(pages, report))
+        }
+    }
+}
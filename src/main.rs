@@ -1,60 +1,552 @@
 pub mod asana;
+pub mod asana_csv;
+pub mod asana_org;
+pub mod attachments;
 pub mod convert;
+pub mod data_files;
+pub mod dependencies;
+pub mod diff;
+pub mod hierarchy;
+pub mod input;
 pub mod mapping;
+pub mod output;
+pub mod projects;
+pub mod replica;
 pub mod taskwarrior;
 mod test;
+pub mod todotxt;
 
-use clap::Parser;
-use std::{fs::File, path::PathBuf};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
 
-use mapping::SectionPriorityMapping;
+use mapping::{AssigneeMapping, SectionPriorityMapping};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
 
-use crate::convert::convert_tasks;
+use crate::{
+    attachments::{copy_attachments, find_local_attachments, LocalAttachment},
+    convert::{
+        convert_tasks_with_uuids, ConversionReport, ConvertError, ConvertOptions, Converter,
+    },
+    data_files::write_data_files,
+    dependencies::{DanglingDependencyPolicy, DependencyCyclePolicy, SubtaskDependencies},
+    diff::diff_exports,
+    input::{
+        for_each_asana_task, get_section_positions, is_stdio, read_asana_tasks, read_projects,
+        read_resources, read_sidecar_resources, read_stories, InputFormat, InputReport, STDIO_PATH,
+    },
+    output::{create_output, OutputFormat, TaskWriter},
+    projects::{ArchivedProjectPolicy, ProjectDueDateTarget, ProjectHierarchy},
+    replica::write_to_replica,
+    taskwarrior::{get_asana_order_taskrc, TaskwarriorVersion},
+};
 
 const VERSION: &str = "0.1.0";
 
 #[derive(Parser)]
-#[clap(version = VERSION, author = "Artiom Khandamirov <t9max@yandex.ru>")]
+#[clap(
+    version = VERSION,
+    author = "Artiom Khandamirov <t9max@yandex.ru>",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct CLIOptions {
-    #[clap(short, long)]
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    /// Input file, directory of pages or `-` for standard input; `.gz`, `.zst` and `.zip` files are
+    /// decompressed
+    #[clap(short, long, default_value = STDIO_PATH)]
     pub input_asana_file: PathBuf,
-    #[clap(short, long)]
+    /// Format of input file
+    #[clap(long, value_enum, default_value_t = InputFormat::Auto)]
+    pub input_format: InputFormat,
+    /// Output file or `-` for standard output
+    #[clap(short, long, default_value = STDIO_PATH)]
     pub output_taskwarrior_file: PathBuf,
+    /// Format of output file
+    #[clap(long, value_enum, default_value_t = OutputFormat::Json)]
+    pub format: OutputFormat,
+    /// Version of Taskwarrior to write tasks for, as `task export` of that version produces them
+    /// (output format of earlier releases of converter by default)
+    #[clap(long, value_enum, value_name = "VERSION", conflicts_with_all = ["taskchampion_replica", "taskwarrior_data_dir"])]
+    pub taskwarrior_version: Option<TaskwarriorVersion>,
+    /// Indent output JSON array, one field per line (JSON format only)
+    #[clap(long)]
+    pub pretty: bool,
+    /// Write taskrc include file with `asana_order` UDA and `asana_board` report definitions
+    #[clap(long, value_name = "FILE", requires = "asana_order")]
+    pub write_taskrc: Option<PathBuf>,
+    /// Write tasks directly into TaskChampion replica (Taskwarrior 3 data directory or
+    /// `taskchampion.sqlite3` file) instead of output file, updating tasks with the same UUID
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["output_taskwarrior_file", "format", "pretty", "stream"])]
+    pub taskchampion_replica: Option<PathBuf>,
+    /// Merge tasks into `pending.data` and `completed.data` of Taskwarrior 2.x data directory
+    /// instead of writing output file, replacing tasks with the same UUID
+    #[clap(long, value_name = "DIR", conflicts_with_all = ["output_taskwarrior_file", "format", "pretty", "stream", "taskchampion_replica"])]
+    pub taskwarrior_data_dir: Option<PathBuf>,
+    /// Read input file twice task by task instead of loading it into memory (JSON file input only)
+    #[clap(long)]
+    pub stream: bool,
+    #[clap(flatten)]
+    pub conversion: ConversionArgs,
+}
+
+#[derive(Args)]
+struct ConversionArgs {
     #[clap(long)]
     pub section_priority_mapping_file: Option<PathBuf>,
+    /// Sections of projects (file or directory of `<project gid>.json` files), gives section
+    /// positions for section priority mapping
+    #[clap(long)]
+    pub sections_file: Option<PathBuf>,
+    /// JSON file with mapping of "My Tasks" assignee status and section to Taskwarrior fields
     #[clap(long)]
+    pub assignee_mapping_file: Option<PathBuf>,
+    /// Same as `--subtask-dependencies parent-blocked-by-children`
+    #[clap(long, conflicts_with = "subtask_dependencies")]
     pub children_to_dependencies: bool,
+    /// How subtasks are turned into dependencies
+    #[clap(long, value_enum)]
+    pub subtask_dependencies: Option<SubtaskDependencies>,
     #[clap(long)]
     pub append_sections_to_project: bool,
+    /// What to do with dependencies on tasks missing from export
+    #[clap(long, value_enum, default_value_t = DanglingDependencyPolicy::Keep)]
+    pub dangling_dependencies: DanglingDependencyPolicy,
+    /// What to do with dependency cycles
+    #[clap(long, value_enum, default_value_t = DependencyCyclePolicy::Break)]
+    pub dependency_cycles: DependencyCyclePolicy,
+    /// Maximum depth of subtasks output as separate tasks (subtasks of top-level tasks have depth 1)
+    #[clap(long)]
+    pub max_subtask_depth: Option<usize>,
+    /// Turn subtasks below maximum depth (all subtasks by default) into annotations
+    #[clap(long)]
+    pub collapse_subtasks_into_annotations: bool,
+    /// Stories of tasks (file or directory of `<task gid>.json` files), comments are turned into
+    /// annotations
+    #[clap(long)]
+    pub stories_file: Option<PathBuf>,
+    /// Turn system stories (assignment changes and so on) into annotations too
+    #[clap(long, requires = "stories_file")]
+    pub include_system_stories: bool,
+    /// Attachments of tasks (file or directory of `<task gid>.json` files), turned into annotations
+    /// with paths of local files
+    #[clap(long)]
+    pub attachments_file: Option<PathBuf>,
+    /// Directory with local files of attachments (directory of attachments file by default)
+    #[clap(long, requires = "attachments_file")]
+    pub attachments_directory: Option<PathBuf>,
+    /// Set `asana_order` UDA with position of tasks on Asana board
+    #[clap(long)]
+    pub asana_order: bool,
+    /// Difference of `asana_order` of first tasks of adjacent sections
+    #[clap(long, default_value_t = 1000, requires = "asana_order")]
+    pub asana_order_stride: u64,
+    /// Copy local files of attachments to `DIR/<task UUID>/<attachment gid>-<name>`
+    #[clap(long, value_name = "DIR", requires = "attachments_file")]
+    pub copy_attachments_to: Option<PathBuf>,
+    /// Projects (projects endpoint output, file or directory of pages), used for archived
+    /// projects, project due dates and project owners
+    #[clap(long)]
+    pub projects_file: Option<PathBuf>,
+    /// What to do with tasks that are only in archived projects
+    #[clap(long, value_enum, default_value_t = ArchivedProjectPolicy::Keep, requires = "projects_file")]
+    pub archived_projects: ArchivedProjectPolicy,
+    /// Taskwarrior field that project due date is set to, for tasks without due date
+    #[clap(long, value_enum, default_value_t = ProjectDueDateTarget::None, requires = "projects_file")]
+    pub project_due_dates: ProjectDueDateTarget,
+    /// Set `asana_project_owner` UDA to name of owner of task's project
+    #[clap(long, requires = "projects_file")]
+    pub project_owners: bool,
+    /// Teams (teams endpoint output with `projects` of each team), team names prefix project
+    /// names
+    #[clap(long)]
+    pub teams_file: Option<PathBuf>,
+    /// Portfolios (portfolios endpoint output with `items` of each portfolio), portfolio names
+    /// prefix project names
+    #[clap(long)]
+    pub portfolios_file: Option<PathBuf>,
+}
+
+impl ConversionArgs {
+    fn into_options(self) -> ConvertOptions {
+        let projects = self
+            .projects_file
+            .map(|path| read_projects_file(&path))
+            .unwrap_or_default();
+        let teams: Vec<asana::Team> = self
+            .teams_file
+            .map(|path| read_resources_file(&path))
+            .unwrap_or_default();
+        let portfolios: Vec<asana::Portfolio> = self
+            .portfolios_file
+            .map(|path| read_resources_file(&path))
+            .unwrap_or_default();
+        ConvertOptions {
+            section_priority_mapping: read_section_priority_mapping(
+                self.section_priority_mapping_file,
+            ),
+            section_positions: self
+                .sections_file
+                .map(|path| read_sections_file(&path))
+                .unwrap_or_default(),
+            subtask_dependencies: if self.children_to_dependencies {
+                SubtaskDependencies::ParentBlockedByChildren
+            } else {
+                self.subtask_dependencies.unwrap_or_default()
+            },
+            append_sections_to_project: self.append_sections_to_project,
+            dangling_dependencies: self.dangling_dependencies,
+            dependency_cycles: self.dependency_cycles,
+            max_subtask_depth: self.max_subtask_depth,
+            collapse_subtasks_into_annotations: self.collapse_subtasks_into_annotations,
+            stories: self
+                .stories_file
+                .map(|path| read_stories_file(&path))
+                .unwrap_or_default(),
+            include_system_stories: self.include_system_stories,
+            attachments: match self.attachments_file {
+                Some(path) => read_attachments_file(&path, self.attachments_directory),
+                None => HashMap::new(),
+            },
+            attachments_copy_directory: self.copy_attachments_to,
+            assignee_mapping: read_assignee_mapping(self.assignee_mapping_file),
+            asana_order_stride: if self.asana_order {
+                Some(self.asana_order_stride)
+            } else {
+                None
+            },
+            reference_time: None,
+            project_hierarchy: ProjectHierarchy::new(&teams, &portfolios, &projects),
+            projects,
+            archived_projects: self.archived_projects,
+            project_due_dates: self.project_due_dates,
+            project_owners: self.project_owners,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compare two Asana exports and show changes of converted Taskwarrior tasks
+    Diff(DiffOptions),
+}
+
+#[derive(Args)]
+struct DiffOptions {
+    pub old_asana_file: PathBuf,
+    pub new_asana_file: PathBuf,
+    /// Format of input files
+    #[clap(long, value_enum, default_value_t = InputFormat::Auto)]
+    pub input_format: InputFormat,
+    /// Print difference as JSON instead of table
+    #[clap(long)]
+    pub json: bool,
+    #[clap(flatten)]
+    pub conversion: ConversionArgs,
+}
+
+fn read_asana_file(path: &Path, format: InputFormat) -> Vec<asana::Task> {
+    let mut report = InputReport::default();
+    let asana_tasks = read_asana_tasks(path, format, &mut report).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    });
+    eprint!("{}", report);
+    asana_tasks
+}
+
+fn read_sections_file(path: &Path) -> HashMap<String, usize> {
+    let sections: HashMap<String, Vec<asana::Section>> = read_sidecar_resources(path)
+        .unwrap_or_else(|err| {
+            eprintln!("{}: {}", path.display(), err);
+            process::exit(1);
+        });
+    get_section_positions(&sections)
+}
+
+fn read_projects_file(path: &Path) -> HashMap<String, asana::Project> {
+    read_projects(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    })
+}
+
+fn read_resources_file<T: DeserializeOwned>(path: &Path) -> Vec<T> {
+    read_resources(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    })
+}
+
+fn read_stories_file(path: &Path) -> HashMap<String, Vec<asana::Story>> {
+    read_stories(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    })
+}
+
+fn read_attachments_file(
+    path: &Path,
+    directory: Option<PathBuf>,
+) -> HashMap<String, Vec<LocalAttachment>> {
+    let attachments = read_sidecar_resources(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    });
+    let directory = directory.unwrap_or_else(|| match path.parent() {
+        Some(parent) if !path.is_dir() => parent.to_path_buf(),
+        _ => path.to_path_buf(),
+    });
+    find_local_attachments(attachments, &directory)
+}
+
+/// Copy attachment files of converted tasks, if it is requested.
+fn copy_attachment_files(options: &ConvertOptions, uuids: &HashMap<String, Uuid>) {
+    if let Some(copy_directory) = &options.attachments_copy_directory {
+        let copied =
+            copy_attachments(&options.attachments, uuids, copy_directory).unwrap_or_else(|err| {
+                eprintln!("{}: {}", copy_directory.display(), err);
+                process::exit(1);
+            });
+        eprintln!("{} attachment files copied", copied);
+    }
+}
+
+fn read_section_priority_mapping(path: Option<PathBuf>) -> SectionPriorityMapping {
+    if let Some(path) = path {
+        File::open(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader(file).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| {
+                eprintln!("{}: {}", path.display(), err);
+                process::exit(1);
+            })
+    } else {
+        SectionPriorityMapping::default()
+    }
+}
+
+fn read_assignee_mapping(path: Option<PathBuf>) -> AssigneeMapping {
+    if let Some(path) = path {
+        File::open(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| serde_json::from_reader(file).map_err(|err| err.to_string()))
+            .unwrap_or_else(|err| {
+                eprintln!("{}: {}", path.display(), err);
+                process::exit(1);
+            })
+    } else {
+        AssigneeMapping::default()
+    }
+}
+
+fn diff(opts: DiffOptions) {
+    let old_asana_tasks = read_asana_file(&opts.old_asana_file, opts.input_format);
+    let new_asana_tasks = read_asana_file(&opts.new_asana_file, opts.input_format);
+    let options = opts.conversion.into_options();
+
+    let exports_diff =
+        diff_exports(old_asana_tasks, new_asana_tasks, &options).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+
+    if opts.json {
+        serde_json::to_writer_pretty(std::io::stdout(), &exports_diff).unwrap();
+        println!();
+    } else {
+        print!("{}", exports_diff);
+    }
+}
+
+/// Create writer of converted tasks to output file, exit on error.
+fn create_task_writer(
+    path: &Path,
+    format: OutputFormat,
+    pretty: bool,
+    version: Option<TaskwarriorVersion>,
+) -> TaskWriter<Box<dyn Write>> {
+    let output = create_output(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    });
+    TaskWriter::with_format(output, format, pretty).with_version(version)
+}
+
+/// Print output error (e.g. closed pipe or full disk) and exit.
+fn exit_on_output_error<T>(path: &Path, err: io::Error) -> T {
+    eprintln!("{}: {}", path.display(), err);
+    process::exit(1);
+}
+
+/// Convert JSON export without loading it into memory: first pass collects dependencies, second
+/// pass converts task trees and writes them to output as they are read.
+fn convert_streaming(
+    input_path: &Path,
+    output_path: &Path,
+    mut task_writer: TaskWriter<Box<dyn Write>>,
+    options: &ConvertOptions,
+) {
+    let exit_on_input_error = |err| {
+        eprintln!("{}: {}", input_path.display(), err);
+        process::exit(1);
+    };
+
+    let mut converter = Converter::new(options, Default::default());
+    let mut input_report = InputReport::default();
+    for_each_asana_task(input_path, &mut input_report, |task| {
+        converter.add_task_tree(&task)
+    })
+    .unwrap_or_else(exit_on_input_error);
+    eprint!("{}", input_report);
+
+    let mut report = ConversionReport::default();
+    converter.prepare(&mut report).unwrap_or_else(|err| {
+        eprint!("{}", report);
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let mut output_tasks = Vec::new();
+    let mut convert_error: Option<ConvertError> = None;
+    for_each_asana_task(input_path, &mut InputReport::default(), |task| {
+        if convert_error.is_some() {
+            return;
+        }
+        match converter.convert_task_tree(task, &mut output_tasks, &mut report) {
+            Ok(()) => task_writer
+                .write_tasks(&output_tasks)
+                .unwrap_or_else(|err| exit_on_output_error(output_path, err)),
+            Err(err) => convert_error = Some(err),
+        }
+        output_tasks.clear();
+    })
+    .unwrap_or_else(exit_on_input_error);
+    if let Some(err) = convert_error {
+        eprint!("{}", report);
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+
+    converter.finish(&mut output_tasks);
+    task_writer
+        .write_tasks(&output_tasks)
+        .unwrap_or_else(|err| exit_on_output_error(output_path, err));
+    task_writer
+        .finish()
+        .unwrap_or_else(|err| exit_on_output_error(output_path, err));
+    eprint!("{}", report);
+    copy_attachment_files(options, &converter.uuids);
 }
 
 fn main() {
     let opts: CLIOptions = CLIOptions::parse();
 
-    let input_asana_data: asana::Exported;
-    {
-        let input_asana_file = File::open(opts.input_asana_file).unwrap();
-        input_asana_data = serde_json::from_reader(input_asana_file).unwrap();
+    if let Some(Command::Diff(diff_opts)) = opts.command {
+        diff(diff_opts);
+        return;
     }
 
-    let section_priority_mapping: SectionPriorityMapping;
-    if let Some(section_priority_mapping_file_path) = opts.section_priority_mapping_file {
-        let section_priority_mapping_file = File::open(section_priority_mapping_file_path).unwrap();
-        section_priority_mapping = serde_json::from_reader(section_priority_mapping_file).unwrap();
-    } else {
-        section_priority_mapping = SectionPriorityMapping::default();
+    let input_asana_file = opts.input_asana_file;
+    let output_taskwarrior_file = opts.output_taskwarrior_file;
+    if opts.pretty && opts.format != OutputFormat::Json {
+        CLIOptions::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--pretty is supported only with --format json",
+            )
+            .exit();
+    }
+    if opts.taskwarrior_version.is_some() && opts.format == OutputFormat::TodoTxt {
+        CLIOptions::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--taskwarrior-version can't be used with --format todo-txt",
+            )
+            .exit();
     }
+    let options = opts.conversion.into_options();
 
-    let output_taskwarrior_data = convert_tasks(
-        input_asana_data.data,
-        &section_priority_mapping,
-        opts.children_to_dependencies,
-        opts.append_sections_to_project,
-    )
-    .unwrap();
+    if let Some(taskrc_path) = &opts.write_taskrc {
+        std::fs::write(taskrc_path, get_asana_order_taskrc()).unwrap_or_else(|err| {
+            eprintln!("{}: {}", taskrc_path.display(), err);
+            process::exit(1);
+        });
+    }
+
+    if opts.stream {
+        if opts.input_format.detect(&input_asana_file) != InputFormat::Json
+            || is_stdio(&input_asana_file)
+        {
+            eprintln!("Streaming conversion supports only JSON input file");
+            process::exit(1);
+        }
+        let task_writer = create_task_writer(
+            &output_taskwarrior_file,
+            opts.format,
+            opts.pretty,
+            opts.taskwarrior_version,
+        );
+        convert_streaming(
+            &input_asana_file,
+            &output_taskwarrior_file,
+            task_writer,
+            &options,
+        );
+        return;
+    }
+
+    let input_asana_tasks = read_asana_file(&input_asana_file, opts.input_format);
 
-    {
-        let output_taskwarrior_file = File::create(opts.output_taskwarrior_file).unwrap();
-        serde_json::to_writer(output_taskwarrior_file, &output_taskwarrior_data).unwrap();
+    let mut report = ConversionReport::default();
+    let mut uuids = HashMap::new();
+    let output_taskwarrior_data =
+        convert_tasks_with_uuids(input_asana_tasks, &mut uuids, &options, &mut report)
+            .unwrap_or_else(|err| {
+                eprint!("{}", report);
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+    eprint!("{}", report);
+    copy_attachment_files(&options, &uuids);
+
+    if let Some(replica_path) = &opts.taskchampion_replica {
+        let replica_report = write_to_replica(replica_path, &output_taskwarrior_data)
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+        eprint!("{}", replica_report);
+        return;
+    }
+
+    if let Some(data_directory) = &opts.taskwarrior_data_dir {
+        let data_files_report = write_data_files(data_directory, &output_taskwarrior_data)
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+        eprint!("{}", data_files_report);
+        return;
     }
+
+    let mut task_writer = create_task_writer(
+        &output_taskwarrior_file,
+        opts.format,
+        opts.pretty,
+        opts.taskwarrior_version,
+    );
+    task_writer
+        .write_tasks(&output_taskwarrior_data)
+        .unwrap_or_else(|err| exit_on_output_error(&output_taskwarrior_file, err));
+    task_writer
+        .finish()
+        .unwrap_or_else(|err| exit_on_output_error(&output_taskwarrior_file, err));
 }
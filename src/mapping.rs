@@ -1,20 +1,169 @@
+use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, convert::TryFrom};
 
-use crate::taskwarrior::Priority;
+use crate::{asana, taskwarrior::Priority};
 
-/// Mapping from Asana section name to Taskwarrior priority of tasks.
+/// Mapping from Asana section to Taskwarrior priority of tasks.
+///
+/// Section is looked up by gid, then by name, then by its position in project, and falls back to
+/// `default_mapping`.
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct SectionPriorityMapping {
     pub default_mapping: Option<Priority>,
+    #[serde(default)]
     pub mapping: HashMap<String, Priority>,
+    /// Mapping from section gid, that doesn't break when section is renamed.
+    #[serde(default)]
+    pub gid_mapping: HashMap<String, Priority>,
+    /// Priority of sections by position in project: first `N` sections get first priority, next
+    /// `M` sections get second one and so on.
+    #[serde(default)]
+    pub position_mapping: Vec<(Priority, usize)>,
 }
 
 impl SectionPriorityMapping {
-    pub fn get_mapping(&self, section_name: &str) -> Option<Priority> {
-        match self.mapping.get(section_name) {
-            Some(priority) => Some(priority.clone()),
-            None => self.default_mapping.clone(),
+    /// Get priority of section, given its position in project if it is known.
+    pub fn get_mapping(
+        &self,
+        section: &asana::SectionReference,
+        position: Option<usize>,
+    ) -> Option<Priority> {
+        if let Some(priority) = self
+            .gid_mapping
+            .get(&section.gid)
+            .or_else(|| self.mapping.get(&section.name))
+        {
+            return Some(priority.clone());
+        }
+        if let Some(position) = position {
+            let mut first_position = 0;
+            for (priority, count) in &self.position_mapping {
+                first_position += count;
+                if position < first_position {
+                    return Some(priority.clone());
+                }
+            }
+        }
+        self.default_mapping.clone()
+    }
+}
+
+/// Date relative to conversion time: `today`, `tomorrow`, `eow` (end of week), `eom` (end of
+/// month), `+Nd` or `+Nw`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum RelativeDate {
+    Today,
+    Tomorrow,
+    EndOfWeek,
+    EndOfMonth,
+    Days(i64),
+}
+
+impl TryFrom<String> for RelativeDate {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let days = |number: &str, multiplier: i64| {
+            number
+                .parse::<i64>()
+                .ok()
+                .and_then(|number| number.checked_mul(multiplier))
+                .filter(|days| Duration::try_days(*days).is_some())
+                .map(RelativeDate::Days)
+                .ok_or_else(|| format!("invalid relative date: {}", value))
+        };
+        match value.as_str() {
+            "today" => Ok(RelativeDate::Today),
+            "tomorrow" => Ok(RelativeDate::Tomorrow),
+            "eow" => Ok(RelativeDate::EndOfWeek),
+            "eom" => Ok(RelativeDate::EndOfMonth),
+            _ => match value.strip_prefix('+') {
+                Some(number) if number.ends_with('d') => days(&number[..number.len() - 1], 1),
+                Some(number) if number.ends_with('w') => days(&number[..number.len() - 1], 7),
+                _ => Err(format!("invalid relative date: {}", value)),
+            },
+        }
+    }
+}
+
+impl From<RelativeDate> for String {
+    fn from(date: RelativeDate) -> Self {
+        match date {
+            RelativeDate::Today => "today".to_string(),
+            RelativeDate::Tomorrow => "tomorrow".to_string(),
+            RelativeDate::EndOfWeek => "eow".to_string(),
+            RelativeDate::EndOfMonth => "eom".to_string(),
+            RelativeDate::Days(days) => format!("+{}d", days),
         }
     }
 }
+
+impl RelativeDate {
+    /// Get absolute date, relative to `now`; days start at midnight and end right before it.
+    /// Dates beyond the representable range are clamped to it.
+    pub fn resolve(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.date_naive();
+        let start_of = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_utc();
+        let end_of = |date: NaiveDate| {
+            date.and_hms_milli_opt(23, 59, 59, 999)
+                .unwrap_or_default()
+                .and_utc()
+        };
+        match self {
+            RelativeDate::Today => start_of(today),
+            RelativeDate::Tomorrow => start_of(today + Days::new(1)),
+            RelativeDate::EndOfWeek => {
+                end_of(today + Days::new(6 - u64::from(today.weekday().num_days_from_monday())))
+            }
+            RelativeDate::EndOfMonth => {
+                let next_month = today
+                    .with_day(1)
+                    .and_then(|first_day| first_day.checked_add_months(Months::new(1)))
+                    .unwrap_or(today);
+                end_of(next_month.pred_opt().unwrap_or(today))
+            }
+            RelativeDate::Days(days) => Duration::try_days(*days)
+                .and_then(|duration| start_of(today).checked_add_signed(duration))
+                .unwrap_or(if *days < 0 {
+                    DateTime::<Utc>::MIN_UTC
+                } else {
+                    DateTime::<Utc>::MAX_UTC
+                }),
+        }
+    }
+}
+
+/// Taskwarrior fields of tasks with given assignee status.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AssigneeStatusMapping {
+    pub scheduled: Option<RelativeDate>,
+    /// Pending tasks waiting until this date get `waiting` status.
+    pub wait: Option<RelativeDate>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Taskwarrior field that "My Tasks" section name is turned into.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AssigneeSectionTarget {
+    #[serde(rename = "none")]
+    #[default]
+    None,
+    #[serde(rename = "tag")]
+    Tag,
+    /// Project of tasks that are not in any Asana project.
+    #[serde(rename = "project")]
+    Project,
+}
+
+/// Mapping of "My Tasks" fields (`assignee_status` and `assignee_section`) to Taskwarrior fields.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AssigneeMapping {
+    /// Mapping from assignee status (`inbox`, `today`, `upcoming`, `later`).
+    #[serde(default)]
+    pub status_mapping: HashMap<String, AssigneeStatusMapping>,
+    #[serde(default)]
+    pub section_target: AssigneeSectionTarget,
+}
@@ -0,0 +1,138 @@
+//! Taskwarrior import file writers
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+
+use serde::Serialize;
+
+use crate::{
+    input::is_stdio,
+    taskwarrior::{self, get_versioned_task, TaskwarriorVersion},
+    todotxt::get_todotxt_line,
+};
+
+/// Create output file (or use standard output for `-`).
+pub fn create_output(path: &Path) -> io::Result<Box<dyn Write>> {
+    if is_stdio(path) {
+        Ok(Box::new(BufWriter::new(io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Format of output file.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// JSON array of tasks, as `task import` expects
+    #[default]
+    Json,
+    /// One task per line, as `task export` produces with `json.array=off`
+    Jsonl,
+    /// todo.txt lines
+    TodoTxt,
+}
+
+/// Writer of Taskwarrior tasks, that outputs tasks as soon as they are converted.
+pub struct TaskWriter<W: Write> {
+    writer: W,
+    format: OutputFormat,
+    pretty: bool,
+    version: Option<TaskwarriorVersion>,
+    tasks_count: usize,
+}
+
+impl<W: Write> TaskWriter<W> {
+    /// Create writer of compact JSON array.
+    pub fn new(writer: W) -> Self {
+        TaskWriter::with_format(writer, OutputFormat::Json, false)
+    }
+
+    /// Create writer of given format, `pretty` indents JSON array (other formats are not indented).
+    pub fn with_format(writer: W, format: OutputFormat, pretty: bool) -> Self {
+        TaskWriter {
+            writer,
+            format,
+            pretty: pretty && format == OutputFormat::Json,
+            version: None,
+            tasks_count: 0,
+        }
+    }
+
+    /// Write tasks as `task export` of given Taskwarrior version produces them.
+    pub fn with_version(mut self, version: Option<TaskwarriorVersion>) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Write single task as next element of array or next line.
+    pub fn write_task(&mut self, task: &taskwarrior::Task) -> io::Result<()> {
+        if self.format == OutputFormat::TodoTxt {
+            writeln!(self.writer, "{}", get_todotxt_line(task))?;
+            self.tasks_count += 1;
+            return Ok(());
+        }
+        match self.version {
+            Some(version) => self.write_value(&get_versioned_task(task, version)?),
+            None => self.write_value(task),
+        }
+    }
+
+    /// Write serialized task as next element of JSON array or next line of JSON lines.
+    fn write_value<T: Serialize>(&mut self, task: &T) -> io::Result<()> {
+        let is_lines = self.format == OutputFormat::Jsonl;
+        match (is_lines, self.pretty) {
+            (false, false) => {
+                let separator: &[u8] = if self.tasks_count == 0 { b"[" } else { b"," };
+                self.writer.write_all(separator)?;
+                serde_json::to_writer(&mut self.writer, task)?;
+            }
+            (false, true) => {
+                let separator: &[u8] = if self.tasks_count == 0 {
+                    b"[\n"
+                } else {
+                    b",\n"
+                };
+                self.writer.write_all(separator)?;
+                let task_json = serde_json::to_string_pretty(task)?;
+                for (index, line) in task_json.lines().enumerate() {
+                    if index > 0 {
+                        self.writer.write_all(b"\n")?;
+                    }
+                    write!(self.writer, "  {}", line)?;
+                }
+            }
+            (true, _) => {
+                serde_json::to_writer(&mut self.writer, task)?;
+                self.writer.write_all(b"\n")?;
+            }
+        }
+        self.tasks_count += 1;
+        Ok(())
+    }
+
+    /// Write all tasks one by one.
+    pub fn write_tasks(&mut self, tasks: &[taskwarrior::Task]) -> io::Result<()> {
+        for task in tasks {
+            self.write_task(task)?;
+        }
+        Ok(())
+    }
+
+    /// Close array (if any) and flush output, returning underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let ending: &[u8] = match (self.format, self.pretty, self.tasks_count) {
+            (OutputFormat::Json, false, 0) => b"[]",
+            (OutputFormat::Json, false, _) => b"]",
+            (OutputFormat::Json, true, 0) => b"[]\n",
+            (OutputFormat::Json, true, _) => b"\n]\n",
+            (OutputFormat::Jsonl, _, _) | (OutputFormat::TodoTxt, _, _) => b"",
+        };
+        self.writer.write_all(ending)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,158 @@
+//! Project metadata of Asana tasks, from projects, teams and portfolios files
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+
+use crate::asana;
+
+/// What to do with tasks that are only in archived projects.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArchivedProjectPolicy {
+    /// Convert tasks as usual.
+    #[default]
+    Keep,
+    /// Don't output tasks and their subtasks.
+    Skip,
+    /// Mark tasks and their subtasks completed.
+    Complete,
+}
+
+/// Taskwarrior field that project due date is set to, for tasks without due date.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProjectDueDateTarget {
+    #[default]
+    None,
+    Due,
+    Until,
+}
+
+/// Check if task is in some projects, and all of them are archived.
+pub fn is_in_archived_project(
+    task: &asana::Task,
+    projects: &HashMap<String, asana::Project>,
+) -> bool {
+    !task.memberships.is_empty()
+        && task.memberships.iter().all(|membership| {
+            projects
+                .get(&membership.project.gid)
+                .is_some_and(|project| project.archived)
+        })
+}
+
+/// Get due date of the first project of task, if it is set.
+pub fn get_project_due_date(
+    task: &asana::Task,
+    projects: &HashMap<String, asana::Project>,
+) -> Option<NaiveDate> {
+    let project = projects.get(&task.memberships.first()?.project.gid)?;
+    project.due_on.or(project.due_date)
+}
+
+/// Get name of owner of the first project of task, if it is known.
+pub fn get_project_owner<'a>(
+    task: &asana::Task,
+    projects: &'a HashMap<String, asana::Project>,
+) -> Option<&'a str> {
+    let project = projects.get(&task.memberships.first()?.project.gid)?;
+    project
+        .owner
+        .as_ref()
+        .map(|owner| owner.name.as_str())
+        .filter(|name| !name.is_empty())
+}
+
+/// Teams and portfolios of projects, that project names are prefixed with.
+#[derive(Clone, Debug, Default)]
+pub struct ProjectHierarchy {
+    /// Team name by project gid.
+    pub teams: HashMap<String, String>,
+    /// Portfolio name by project gid.
+    pub portfolios: HashMap<String, String>,
+}
+
+impl ProjectHierarchy {
+    /// Find teams and portfolios of projects.
+    ///
+    /// Team of project is taken from `projects` of teams, or from `team` of project in projects
+    /// file. Project in several portfolios gets the first one.
+    pub fn new(
+        teams: &[asana::Team],
+        portfolios: &[asana::Portfolio],
+        projects: &HashMap<String, asana::Project>,
+    ) -> Self {
+        let mut hierarchy = ProjectHierarchy::default();
+        for team in teams {
+            for project in &team.projects {
+                hierarchy
+                    .teams
+                    .entry(project.gid.clone())
+                    .or_insert_with(|| team.name.clone());
+            }
+        }
+        if !teams.is_empty() {
+            for project in projects.values() {
+                let team = match &project.team {
+                    Some(team) => team,
+                    None => continue,
+                };
+                let team_name = teams
+                    .iter()
+                    .find(|known_team| known_team.gid == team.gid)
+                    .map_or(&team.name, |known_team| &known_team.name);
+                if !team_name.is_empty() {
+                    hierarchy
+                        .teams
+                        .entry(project.gid.clone())
+                        .or_insert_with(|| team_name.clone());
+                }
+            }
+        }
+        for portfolio in portfolios {
+            for item in &portfolio.items {
+                hierarchy
+                    .portfolios
+                    .entry(item.gid.clone())
+                    .or_insert_with(|| portfolio.name.clone());
+            }
+        }
+        hierarchy
+    }
+
+    /// Get name of project prefixed with its team and portfolio: `Team.Portfolio.Project`.
+    ///
+    /// Dots in team and portfolio names are replaced, so that they don't add hierarchy levels.
+    pub fn get_project_name(&self, project: &asana::ProjectReference) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(self.teams.get(&project.gid).map(|team| escape_level(team)));
+        parts.extend(
+            self.portfolios
+                .get(&project.gid)
+                .map(|portfolio| escape_level(portfolio)),
+        );
+        parts.push(project.name.clone());
+        parts.join(".")
+    }
+
+    /// Get name of project with section appended: `Team.Portfolio.Project.Section`, or
+    /// `Project. Section` if project has neither team nor portfolio.
+    pub fn get_section_project_name(
+        &self,
+        project: &asana::ProjectReference,
+        section: &asana::SectionReference,
+    ) -> String {
+        let separator = if self.teams.contains_key(&project.gid)
+            || self.portfolios.contains_key(&project.gid)
+        {
+            "."
+        } else {
+            ". "
+        };
+        self.get_project_name(project) + separator + &escape_level(&section.name)
+    }
+}
+
+/// Replace dots in name of a single level of project hierarchy.
+fn escape_level(name: &str) -> String {
+    name.replace('.', "_")
+}
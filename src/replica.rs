@@ -0,0 +1,171 @@
+//! Writing Taskwarrior tasks directly into TaskChampion replica (Taskwarrior 3 task database)
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use taskchampion::{storage::AccessMode, Operation, Operations, Replica, StorageConfig, TaskData};
+
+use crate::taskwarrior::{self, get_task_properties, InvalidDependency, CONVERTER_UDAS};
+
+/// Name of TaskChampion SQLite database file in replica directory.
+pub const REPLICA_FILE_NAME: &str = "taskchampion.sqlite3";
+
+/// Properties of Taskwarrior tasks that are replaced on update, together with `tag_*`, `dep_*`
+/// and `annotation_*` properties and UDAs of converter. Other properties (UDAs of other tools) are
+/// kept.
+const MANAGED_PROPERTIES: [&str; 17] = [
+    "status",
+    "entry",
+    "description",
+    "start",
+    "end",
+    "due",
+    "until",
+    "wait",
+    "modified",
+    "scheduled",
+    "recur",
+    "mask",
+    "imask",
+    "parent",
+    "project",
+    "priority",
+    "depends",
+];
+
+#[derive(Debug)]
+pub enum ReplicaError {
+    TaskChampion(taskchampion::Error),
+    InvalidPath(PathBuf),
+    InvalidDependency(InvalidDependency),
+}
+
+impl From<taskchampion::Error> for ReplicaError {
+    fn from(err: taskchampion::Error) -> Self {
+        ReplicaError::TaskChampion(err)
+    }
+}
+
+impl From<InvalidDependency> for ReplicaError {
+    fn from(err: InvalidDependency) -> Self {
+        ReplicaError::InvalidDependency(err)
+    }
+}
+
+impl Display for ReplicaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplicaError::TaskChampion(err) => write!(f, "{}", err),
+            ReplicaError::InvalidPath(path) => write!(
+                f,
+                "{}: replica must be a directory or {} file",
+                path.display(),
+                REPLICA_FILE_NAME
+            ),
+            ReplicaError::InvalidDependency(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Numbers of tasks created, updated and left unchanged in replica.
+#[derive(Clone, Debug, Default)]
+pub struct ReplicaReport {
+    pub created_tasks: usize,
+    pub updated_tasks: usize,
+    pub unchanged_tasks: usize,
+}
+
+impl Display for ReplicaReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} tasks created, {} tasks updated, {} tasks unchanged in TaskChampion replica",
+            self.created_tasks, self.updated_tasks, self.unchanged_tasks
+        )
+    }
+}
+
+/// Get replica directory from path of directory or `taskchampion.sqlite3` file.
+fn get_replica_directory(path: &Path) -> Result<PathBuf, ReplicaError> {
+    if path.file_name().and_then(|name| name.to_str()) == Some(REPLICA_FILE_NAME) {
+        match path.parent() {
+            Some(parent) if parent.as_os_str().is_empty() => Ok(PathBuf::from(".")),
+            Some(parent) => Ok(parent.to_path_buf()),
+            None => Err(ReplicaError::InvalidPath(path.to_path_buf())),
+        }
+    } else if path.is_file() {
+        Err(ReplicaError::InvalidPath(path.to_path_buf()))
+    } else {
+        Ok(path.to_path_buf())
+    }
+}
+
+/// Check if property is set by converter and should be removed when converted task lacks it.
+fn is_managed_property(property: &str) -> bool {
+    MANAGED_PROPERTIES.contains(&property)
+        || CONVERTER_UDAS.contains(&property)
+        || property.starts_with("tag_")
+        || property.starts_with("dep_")
+        || property.starts_with("annotation_")
+}
+
+/// Write tasks into TaskChampion replica (created if missing) in a single transaction.
+///
+/// Tasks already in replica with the same UUID are updated in place: their properties set by
+/// converter are replaced, other properties are kept. Tasks whose properties did not change are
+/// left as they are. Write can be undone with `task undo`.
+pub fn write_to_replica(
+    path: &Path,
+    tasks: &[taskwarrior::Task],
+) -> Result<ReplicaReport, ReplicaError> {
+    let storage = StorageConfig::OnDisk {
+        taskdb_dir: get_replica_directory(path)?,
+        create_if_missing: true,
+        access_mode: AccessMode::ReadWrite,
+    }
+    .into_storage()?;
+    let mut replica = Replica::new(storage);
+
+    let mut report = ReplicaReport::default();
+    let mut operations = Operations::new();
+    operations.push(Operation::UndoPoint);
+    for task in tasks {
+        let properties = get_task_properties(task)?;
+        let (mut task_data, is_created) = match replica.get_task_data(task.uuid.uuid)? {
+            Some(task_data) => (task_data, false),
+            None => (TaskData::create(task.uuid.uuid, &mut operations), true),
+        };
+        let removed_properties: Vec<String> = task_data
+            .properties()
+            .filter(|property| {
+                is_managed_property(property) && !properties.contains_key(property.as_str())
+            })
+            .cloned()
+            .collect();
+        // Modification time of converted task is the time of conversion, so it is not updated
+        // alone: tasks that did not change keep their modification time and are not synced again
+        let is_changed = !removed_properties.is_empty()
+            || properties.iter().any(|(property, value)| {
+                property != "modified" && task_data.get(property) != Some(value.as_str())
+            });
+        if is_created {
+            report.created_tasks += 1;
+        } else if is_changed {
+            report.updated_tasks += 1;
+        } else {
+            report.unchanged_tasks += 1;
+            continue;
+        }
+        for property in removed_properties {
+            task_data.update(property, None, &mut operations);
+        }
+        for (property, value) in properties {
+            if task_data.get(&property) != Some(value.as_str()) {
+                task_data.update(property, Some(value), &mut operations);
+            }
+        }
+    }
+    replica.commit_operations(operations)?;
+    Ok(report)
+}
@@ -1,12 +1,17 @@
 //! Taskwarrior format types
-use std::{collections::HashSet, fmt::Display};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
 
 use chrono::{DateTime, Utc};
+use clap::ValueEnum;
 use itertools::Itertools;
 use serde::{
     de::{Unexpected, Visitor},
     Deserialize, Serialize,
 };
+use serde_json::Value;
 use sscanf::scanf;
 use uuid::Uuid;
 
@@ -44,12 +49,13 @@ impl Display for UUID {
     }
 }
 
+/// Get `depends` field of dependencies: unique UUIDs, sorted so that output is stable.
 pub fn get_depends_fields(uuids: Vec<Uuid>) -> String {
-    let uuid_strings_set: HashSet<String> = uuids
+    let uuid_strings_set: BTreeSet<String> = uuids
         .iter()
         .map(|&uuid| UUID::new(uuid).to_string())
         .collect();
-    return uuid_strings_set.iter().join(",");
+    uuid_strings_set.iter().join(",")
 }
 
 impl Serialize for UUID {
@@ -142,7 +148,19 @@ pub struct Task {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub annotation: Option<Vec<String>>,
+    pub annotations: Option<Vec<Annotation>>,
+    /// User defined attributes.
+    #[serde(flatten)]
+    pub udas: BTreeMap<String, Value>,
+    /// Gid of Asana task that task is converted from, not written to Taskwarrior JSON.
+    #[serde(skip)]
+    pub asana_gid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    pub entry: DateTime<Utc>,
+    pub description: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -168,3 +186,221 @@ pub enum Priority {
     #[serde(rename = "L")]
     Low,
 }
+
+/// Version of Taskwarrior that output is made for.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskwarriorVersion {
+    /// `depends` is comma-joined string, tasks may have waiting status
+    #[value(name = "2.5")]
+    V2_5,
+    /// `depends` is array, waiting tasks are pending tasks with `wait`
+    #[value(name = "2.6")]
+    V2_6,
+    /// Same as 2.6, imported into TaskChampion database
+    #[value(name = "3", alias = "3.x")]
+    V3,
+}
+
+/// Fields of task with dates.
+const DATE_FIELDS: [&str; 8] = [
+    "entry",
+    "start",
+    "end",
+    "due",
+    "until",
+    "wait",
+    "modified",
+    "scheduled",
+];
+
+/// Format date as `task export` does: `YYYYMMDDTHHMMSSZ`.
+fn format_export_date(value: &mut Value) {
+    if let Some(date) = value
+        .as_str()
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+    {
+        *value = Value::from(
+            date.with_timezone(&Utc)
+                .format("%Y%m%dT%H%M%SZ")
+                .to_string(),
+        );
+    }
+}
+
+/// Get JSON of task as `task export` of given Taskwarrior version produces: lowercase UUIDs,
+/// export date format, `depends` and status as this version has them.
+pub fn get_versioned_task(task: &Task, version: TaskwarriorVersion) -> serde_json::Result<Value> {
+    let mut value = serde_json::to_value(task)?;
+    let fields = match value.as_object_mut() {
+        Some(fields) => fields,
+        None => return Ok(value),
+    };
+    for field in ["uuid", "parent"].iter() {
+        if let Some(Value::String(uuid)) = fields.get_mut(*field) {
+            *uuid = uuid.to_lowercase();
+        }
+    }
+    for field in DATE_FIELDS.iter() {
+        if let Some(date) = fields.get_mut(*field) {
+            format_export_date(date);
+        }
+    }
+    if let Some(Value::Array(annotations)) = fields.get_mut("annotations") {
+        for annotation in annotations {
+            if let Some(entry) = annotation.get_mut("entry") {
+                format_export_date(entry);
+            }
+        }
+    }
+    if let Some(depends) = fields.remove("depends") {
+        let mut dependencies: Vec<String> = depends
+            .as_str()
+            .unwrap_or_default()
+            .split(',')
+            .filter(|uuid| !uuid.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        dependencies.sort();
+        let depends = match version {
+            TaskwarriorVersion::V2_5 => Value::from(dependencies.join(",")),
+            TaskwarriorVersion::V2_6 | TaskwarriorVersion::V3 => Value::from(dependencies),
+        };
+        fields.insert("depends".to_string(), depends);
+    }
+    // Empty lists are not exported
+    for field in ["tags", "annotations"].iter() {
+        if fields
+            .get(*field)
+            .and_then(Value::as_array)
+            .is_some_and(Vec::is_empty)
+        {
+            fields.remove(*field);
+        }
+    }
+    if version != TaskwarriorVersion::V2_5 && task.status == Status::Waiting {
+        fields.insert("status".to_string(), Value::from("pending"));
+    }
+    Ok(value)
+}
+
+/// Dependency of task that is not a UUID.
+#[derive(Debug)]
+pub struct InvalidDependency(pub String);
+
+impl Display for InvalidDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid UUID in dependencies: {}", self.0)
+    }
+}
+
+/// Get TaskChampion representation of time: Unix timestamp in seconds.
+fn get_timestamp(time: &DateTime<Utc>) -> String {
+    time.timestamp().to_string()
+}
+
+/// Get properties of task as TaskChampion stores them (`task import` of Taskwarrior 3 sets them
+/// this way): dates are timestamps, each dependency, tag and annotation is a property.
+pub fn get_task_properties(task: &Task) -> Result<BTreeMap<String, String>, InvalidDependency> {
+    let mut properties = BTreeMap::new();
+    let status = match task.status {
+        // Taskwarrior 3 has no waiting status, waiting tasks are pending tasks with `wait`
+        Status::Pending | Status::Waiting => "pending",
+        Status::Completed => "completed",
+        Status::Deleted => "deleted",
+        Status::Recurring => "recurring",
+    };
+    properties.insert("status".to_string(), status.to_string());
+    properties.insert("entry".to_string(), get_timestamp(&task.entry));
+    properties.insert("description".to_string(), task.description.clone());
+    let dates = [
+        ("start", &task.start),
+        ("end", &task.end),
+        ("due", &task.due),
+        ("until", &task.until),
+        ("wait", &task.wait),
+        ("scheduled", &task.scheduled),
+    ];
+    for (name, date) in dates.iter() {
+        if let Some(date) = date {
+            properties.insert(name.to_string(), get_timestamp(date));
+        }
+    }
+    let modified = task.modified.unwrap_or_else(Utc::now);
+    properties.insert("modified".to_string(), get_timestamp(&modified));
+    if let Some(recur) = &task.recur {
+        properties.insert("recur".to_string(), recur.clone());
+    }
+    if let Some(mask) = &task.mask {
+        properties.insert("mask".to_string(), mask.clone());
+    }
+    if let Some(imask) = task.imask {
+        properties.insert("imask".to_string(), imask.to_string());
+    }
+    if let Some(parent) = &task.parent {
+        properties.insert("parent".to_string(), parent.uuid.to_string());
+    }
+    if let Some(project) = &task.project {
+        properties.insert("project".to_string(), project.clone());
+    }
+    if let Some(priority) = &task.priority {
+        let priority = match priority {
+            Priority::High => "H",
+            Priority::Medium => "M",
+            Priority::Low => "L",
+        };
+        properties.insert("priority".to_string(), priority.to_string());
+    }
+    for dependency in task.depends.iter().flat_map(|depends| depends.split(',')) {
+        let dependency =
+            Uuid::parse_str(dependency).map_err(|_| InvalidDependency(dependency.to_string()))?;
+        properties.insert(format!("dep_{}", dependency), String::new());
+    }
+    for tag in task.tags.iter().flatten() {
+        properties.insert(format!("tag_{}", tag), String::new());
+    }
+    for annotation in task.annotations.iter().flatten() {
+        // Annotations are keyed by time, annotations made in the same second are moved apart
+        let mut timestamp = annotation.entry.timestamp();
+        while properties.contains_key(&format!("annotation_{}", timestamp)) {
+            timestamp += 1;
+        }
+        properties.insert(
+            format!("annotation_{}", timestamp),
+            annotation.description.clone(),
+        );
+    }
+    for (name, value) in &task.udas {
+        let value = match value {
+            Value::Null => continue,
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        properties.insert(name.clone(), value);
+    }
+    Ok(properties)
+}
+
+/// Name of UDA with position of task on Asana board.
+pub const ASANA_ORDER_UDA: &str = "asana_order";
+
+/// Name of UDA with name of owner of task's Asana project.
+pub const PROJECT_OWNER_UDA: &str = "asana_project_owner";
+
+/// UDAs that converter sets on tasks.
+pub const CONVERTER_UDAS: [&str; 2] = [ASANA_ORDER_UDA, PROJECT_OWNER_UDA];
+
+/// Get taskrc definitions of `asana_order` UDA and `asana_board` report that sorts tasks by it.
+pub fn get_asana_order_taskrc() -> String {
+    [
+        format!("uda.{}.type=numeric", ASANA_ORDER_UDA),
+        format!("uda.{}.label=Asana order", ASANA_ORDER_UDA),
+        "report.asana_board.description=Pending tasks in Asana board order".to_string(),
+        "report.asana_board.columns=id,project,priority,due,description.count".to_string(),
+        "report.asana_board.labels=ID,Project,Pri,Due,Description".to_string(),
+        "report.asana_board.filter=status:pending".to_string(),
+        format!("report.asana_board.sort=project+,{}+", ASANA_ORDER_UDA),
+    ]
+    .iter()
+    .map(|line| line.to_string() + "\n")
+    .collect()
+}
@@ -1,7 +1,37 @@
 #[cfg(test)]
 mod tests {
-    use crate::{asana, convert::convert_tasks, mapping::SectionPriorityMapping, taskwarrior};
-    use std::{collections::HashMap, fs::File, path::Path};
+    use crate::{
+        asana, asana_csv,
+        attachments::{copy_attachments, find_local_attachments},
+        convert::{
+            convert_tasks, convert_tasks_with_uuids, ConversionReport, ConvertError,
+            ConvertOptions, Converter,
+        },
+        data_files::{get_f4_line, try_lock, write_data_files, DataFilesError},
+        dependencies::{DanglingDependencyPolicy, DependencyCyclePolicy, SubtaskDependencies},
+        diff::{diff_exports, TaskChangeKind, TaskwarriorChangeKind},
+        input::{
+            for_each_asana_task, get_section_positions, read_asana_tasks, read_projects,
+            read_resources, read_sidecar_resources, read_stories, InputFormat, InputReport,
+        },
+        mapping::{AssigneeMapping, SectionPriorityMapping},
+        output::{OutputFormat, TaskWriter},
+        projects::{ArchivedProjectPolicy, ProjectDueDateTarget, ProjectHierarchy},
+        replica::write_to_replica,
+        taskwarrior::{
+            self, get_asana_order_taskrc, get_depends_fields, get_task_properties,
+            get_versioned_task, Priority, TaskwarriorVersion, ASANA_ORDER_UDA, PROJECT_OWNER_UDA,
+        },
+        todotxt::get_todotxt_line,
+    };
+    use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+    use std::{
+        collections::HashMap,
+        fs::File,
+        io::{Read, Write},
+        path::Path,
+    };
+    use uuid::Uuid;
 
     fn assert_tasks_equal(task1: &taskwarrior::Task, task2: &taskwarrior::Task) {
         assert_eq!(task1.status, task2.status);
@@ -23,6 +53,19 @@ mod tests {
         assert_eq!(task1.tags, task2.tags);
     }
 
+    fn read_test_input() -> asana::Exported {
+        let input_file_path = Path::new("testfiles/input/1194733031423185.json");
+        let input_asana_file = File::open(input_file_path).unwrap();
+        serde_json::from_reader(input_asana_file).unwrap()
+    }
+
+    fn task_reference(gid: &str) -> asana::TaskReference {
+        asana::TaskReference {
+            gid: gid.to_string(),
+            resource_type: "task".to_string(),
+        }
+    }
+
     #[test]
     fn test1() {
         let input_file_path = Path::new("testfiles/input/1194733031423185.json");
@@ -37,10 +80,22 @@ mod tests {
         let section_priority_mapping = SectionPriorityMapping {
             default_mapping: None,
             mapping: HashMap::new(),
+            ..SectionPriorityMapping::default()
         };
 
-        let output_taskwarrior_data =
-            convert_tasks(input_asana_data.data, &section_priority_mapping, true, true).unwrap();
+        let options = ConvertOptions {
+            section_priority_mapping,
+            subtask_dependencies: SubtaskDependencies::ParentBlockedByChildren,
+            append_sections_to_project: true,
+            ..ConvertOptions::default()
+        };
+
+        let output_taskwarrior_data = convert_tasks(
+            input_asana_data.data,
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
 
         let correct_output_data: Vec<taskwarrior::Task>;
         {
@@ -58,4 +113,1479 @@ mod tests {
 
         // TODO!
     }
+
+    #[test]
+    fn test_diff() {
+        let old_asana_data = read_test_input();
+
+        let mut new_tasks = old_asana_data.data.clone();
+        let removed_task = new_tasks.remove(0);
+        new_tasks[0].name = "Renamed task".to_string();
+        new_tasks[6].completed = true;
+        new_tasks[6].completed_at = Some(new_tasks[6].modified_at);
+
+        let options = ConvertOptions {
+            append_sections_to_project: true,
+            ..ConvertOptions::default()
+        };
+
+        let exports_diff = diff_exports(old_asana_data.data, new_tasks, &options).unwrap();
+
+        let asana_changes: Vec<(TaskChangeKind, &str)> = exports_diff
+            .asana
+            .iter()
+            .map(|change| (change.kind.clone(), change.gid.as_str()))
+            .collect();
+        assert_eq!(asana_changes.len(), 3);
+        assert!(asana_changes.contains(&(TaskChangeKind::Removed, removed_task.gid.as_str())));
+        assert!(asana_changes
+            .iter()
+            .any(|(kind, _)| *kind == TaskChangeKind::Renamed));
+        assert!(asana_changes
+            .iter()
+            .any(|(kind, _)| *kind == TaskChangeKind::Completed));
+
+        let taskwarrior_changes: Vec<&TaskwarriorChangeKind> = exports_diff
+            .taskwarrior
+            .iter()
+            .map(|change| &change.kind)
+            .collect();
+        assert_eq!(
+            taskwarrior_changes,
+            vec![
+                &TaskwarriorChangeKind::Modified,
+                &TaskwarriorChangeKind::Modified,
+                &TaskwarriorChangeKind::Orphaned
+            ]
+        );
+        assert_eq!(
+            exports_diff.taskwarrior[2].uuid.to_string(),
+            exports_diff.taskwarrior[2].uuid.to_string().to_uppercase()
+        );
+
+        // Unchanged dependencies are serialized in the same order and are not reported
+        let mut asana_tasks = read_test_input().data;
+        let dependencies = asana_tasks[..7]
+            .iter()
+            .map(|task| task_reference(&task.gid))
+            .collect();
+        asana_tasks[7].dependencies = Some(dependencies);
+        let exports_diff = diff_exports(asana_tasks.clone(), asana_tasks, &options).unwrap();
+        assert!(exports_diff.taskwarrior.is_empty());
+
+        // Subtask exported nested and then top-level keeps priority of section of its parent,
+        // like converted task does
+        let mut nested_tasks = read_test_input().data;
+        let mut subtask = nested_tasks.remove(1);
+        subtask.parent = Some(task_reference(&nested_tasks[0].gid));
+        let mut flat_tasks = nested_tasks.clone();
+        nested_tasks[0].subtasks = Some(vec![subtask.clone()]);
+        subtask.memberships[0].section.name = "Urgent".to_string();
+        flat_tasks.push(subtask);
+        let options = ConvertOptions {
+            section_priority_mapping: serde_json::from_str(r#"{"mapping": {"Urgent": "H"}}"#)
+                .unwrap(),
+            ..ConvertOptions::default()
+        };
+        let exports_diff = diff_exports(nested_tasks, flat_tasks, &options).unwrap();
+        let asana_changes: Vec<&TaskChangeKind> = exports_diff
+            .asana
+            .iter()
+            .map(|change| &change.kind)
+            .collect();
+        assert_eq!(asana_changes, vec![&TaskChangeKind::MovedSection]);
+        assert!(exports_diff.taskwarrior.is_empty());
+    }
+
+    #[test]
+    fn test_depends_fields_order() {
+        let uuids: Vec<Uuid> = (0..10).map(|_| Uuid::new_v4()).collect();
+        let mut reversed_uuids = uuids.clone();
+        reversed_uuids.reverse();
+        reversed_uuids.push(uuids[0]);
+        let depends = get_depends_fields(uuids);
+        assert_eq!(depends, get_depends_fields(reversed_uuids));
+        let depends_uuids: Vec<&str> = depends.split(',').collect();
+        assert_eq!(depends_uuids.len(), 10);
+        assert!(depends_uuids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_dependents_and_dangling_dependencies() {
+        let mut asana_tasks = read_test_input().data;
+        let first_gid = asana_tasks[0].gid.clone();
+        asana_tasks[1].dependents = Some(vec![task_reference(&first_gid)]);
+        asana_tasks[2].dependencies = Some(vec![task_reference("404")]);
+
+        for (policy, output_len, third_depends) in [
+            (DanglingDependencyPolicy::Drop, 8, false),
+            (DanglingDependencyPolicy::Keep, 8, true),
+            (DanglingDependencyPolicy::Placeholder, 9, true),
+        ] {
+            let options = ConvertOptions {
+                dangling_dependencies: policy,
+                ..ConvertOptions::default()
+            };
+            let mut report = ConversionReport::default();
+            let output_taskwarrior_data =
+                convert_tasks(asana_tasks.clone(), &options, &mut report).unwrap();
+
+            assert_eq!(output_taskwarrior_data.len(), output_len);
+            assert_eq!(
+                output_taskwarrior_data[0].depends,
+                Some(output_taskwarrior_data[1].uuid.to_string())
+            );
+            assert_eq!(output_taskwarrior_data[2].depends.is_some(), third_depends);
+            assert_eq!(report.dangling_dependencies.len(), 1);
+            assert_eq!(report.dangling_dependencies[0].0.dependency_gid, "404");
+            if policy == DanglingDependencyPolicy::Placeholder {
+                // Placeholder is created when its dependent task was created
+                assert_eq!(output_taskwarrior_data[8].entry, asana_tasks[2].created_at);
+            }
+        }
+
+        // Tasks without name are not output, so dependencies on them are dangling
+        let blank_gid = asana_tasks[3].gid.clone();
+        asana_tasks[3].name = String::new();
+        asana_tasks[4].dependencies = Some(vec![task_reference(&blank_gid)]);
+        let options = ConvertOptions {
+            dangling_dependencies: DanglingDependencyPolicy::Drop,
+            ..ConvertOptions::default()
+        };
+        let mut report = ConversionReport::default();
+        let output_taskwarrior_data = convert_tasks(asana_tasks, &options, &mut report).unwrap();
+        assert_eq!(output_taskwarrior_data.len(), 7);
+        assert_eq!(output_taskwarrior_data[3].depends, None);
+        assert_eq!(report.dangling_dependencies.len(), 2);
+        assert_eq!(report.dangling_dependencies[1].0.dependency_gid, blank_gid);
+    }
+
+    #[test]
+    fn test_dependency_cycles() {
+        let mut asana_tasks = read_test_input().data;
+        let gids: Vec<String> = asana_tasks.iter().map(|task| task.gid.clone()).collect();
+        asana_tasks[0].dependencies = Some(vec![task_reference(&gids[1])]);
+        asana_tasks[1].dependencies = Some(vec![task_reference(&gids[2])]);
+        asana_tasks[2].dependencies = Some(vec![task_reference(&gids[0])]);
+
+        let options = ConvertOptions {
+            dependency_cycles: DependencyCyclePolicy::Fail,
+            ..ConvertOptions::default()
+        };
+        let result = convert_tasks(
+            asana_tasks.clone(),
+            &options,
+            &mut ConversionReport::default(),
+        );
+        match result {
+            Err(ConvertError::DependencyCycle(cycle)) => {
+                assert_eq!(cycle.len(), 4);
+                assert_eq!(cycle.first(), cycle.last());
+            }
+            _ => panic!("Dependency cycle is not detected"),
+        }
+
+        let options = ConvertOptions {
+            dependency_cycles: DependencyCyclePolicy::Break,
+            ..ConvertOptions::default()
+        };
+        let mut report = ConversionReport::default();
+        let output_taskwarrior_data = convert_tasks(asana_tasks, &options, &mut report).unwrap();
+        assert!(output_taskwarrior_data[0].depends.is_some());
+        assert!(output_taskwarrior_data[1].depends.is_some());
+        assert!(output_taskwarrior_data[2].depends.is_none());
+        assert_eq!(report.broken_dependency_cycles.len(), 1);
+        assert_eq!(report.broken_dependency_cycles[0].1.task_gid, gids[2]);
+    }
+
+    #[test]
+    fn test_subtask_dependencies() {
+        let mut asana_tasks = read_test_input().data;
+        let mut parent = asana_tasks.pop().unwrap();
+        parent.subtasks = Some(asana_tasks.drain(0..3).collect());
+
+        for (mode, expected_depends) in [
+            (SubtaskDependencies::None, [0, 0, 0, 0]),
+            (SubtaskDependencies::ParentBlockedByChildren, [3, 0, 0, 0]),
+            (SubtaskDependencies::ChildrenBlockedByParent, [0, 1, 1, 1]),
+            (SubtaskDependencies::Sequential, [0, 0, 1, 1]),
+        ] {
+            let options = ConvertOptions {
+                subtask_dependencies: mode,
+                ..ConvertOptions::default()
+            };
+            let output_taskwarrior_data = convert_tasks(
+                vec![parent.clone()],
+                &options,
+                &mut ConversionReport::default(),
+            )
+            .unwrap();
+
+            let depends: Vec<usize> = output_taskwarrior_data
+                .iter()
+                .map(|task| {
+                    task.depends
+                        .as_ref()
+                        .map_or(0, |depends| depends.split(',').count())
+                })
+                .collect();
+            assert_eq!(depends, expected_depends);
+            if mode == SubtaskDependencies::Sequential {
+                assert_eq!(
+                    output_taskwarrior_data[3].depends,
+                    Some(output_taskwarrior_data[2].uuid.to_string())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_collapse_subtasks_into_annotations() {
+        let mut asana_tasks = read_test_input().data;
+        let mut parent = asana_tasks.pop().unwrap();
+        let mut subtask = asana_tasks.remove(0);
+        subtask.subtasks = Some(asana_tasks.drain(0..2).collect());
+        parent.subtasks = Some(vec![subtask]);
+        let subtask_gid = parent.subtasks.as_ref().unwrap()[0].gid.clone();
+        asana_tasks[0].dependencies = Some(vec![task_reference(&subtask_gid)]);
+        asana_tasks.insert(0, parent);
+
+        let options = ConvertOptions {
+            max_subtask_depth: Some(1),
+            collapse_subtasks_into_annotations: true,
+            ..ConvertOptions::default()
+        };
+        let mut report = ConversionReport::default();
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks.clone(), &options, &mut report).unwrap();
+        assert_eq!(output_taskwarrior_data.len(), 6);
+        let annotations = output_taskwarrior_data[1].annotations.as_ref().unwrap();
+        assert_eq!(annotations.len(), 2);
+        assert!(annotations[0].description.starts_with("[x] "));
+        assert_eq!(report.collapsed_subtasks, 2);
+
+        let options = ConvertOptions {
+            collapse_subtasks_into_annotations: true,
+            ..ConvertOptions::default()
+        };
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks, &options, &mut ConversionReport::default()).unwrap();
+        assert_eq!(output_taskwarrior_data.len(), 5);
+        assert_eq!(
+            output_taskwarrior_data[0]
+                .annotations
+                .as_ref()
+                .map(Vec::len),
+            Some(3)
+        );
+        assert_eq!(
+            output_taskwarrior_data[1].depends,
+            Some(output_taskwarrior_data[0].uuid.to_string())
+        );
+    }
+
+    #[test]
+    fn test_csv_input() {
+        let input_file_path = Path::new("testfiles/input/csv_export.csv");
+        let asana_tasks = asana_csv::read_tasks(File::open(input_file_path).unwrap()).unwrap();
+
+        assert_eq!(asana_tasks.len(), 2);
+        let subtasks = asana_tasks[0].subtasks.as_ref().unwrap();
+        assert_eq!(subtasks.len(), 2);
+        assert_eq!(subtasks[1].subtasks.as_ref().unwrap().len(), 1);
+        assert_eq!(subtasks[1].notes, "Notes with \"quotes\", and commas");
+
+        let options = ConvertOptions {
+            append_sections_to_project: true,
+            ..ConvertOptions::default()
+        };
+        let mut report = ConversionReport::default();
+        let output_taskwarrior_data = convert_tasks(asana_tasks, &options, &mut report).unwrap();
+
+        let descriptions: Vec<&str> = output_taskwarrior_data
+            .iter()
+            .map(|task| task.description.as_str())
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                "Write thesis",
+                "Collect sources",
+                "Write introduction",
+                "Check spelling",
+                "Prepare slides"
+            ]
+        );
+        assert_eq!(
+            output_taskwarrior_data[3].project.as_deref(),
+            Some("Thesis. Writing")
+        );
+        assert_eq!(output_taskwarrior_data[0].tags.as_ref().unwrap().len(), 2);
+        assert_eq!(
+            output_taskwarrior_data[1].status,
+            taskwarrior::Status::Completed
+        );
+        assert_eq!(
+            output_taskwarrior_data[2].depends,
+            Some(output_taskwarrior_data[1].uuid.to_string())
+        );
+        assert_eq!(
+            output_taskwarrior_data[4].depends,
+            Some(output_taskwarrior_data[0].uuid.to_string())
+        );
+        assert!(report.dangling_dependencies.is_empty());
+
+        // Sections of CSV export get stable gids, so that asana_order tells them apart
+        let asana_tasks = asana_csv::read_tasks(File::open(input_file_path).unwrap()).unwrap();
+        let section_gids: Vec<&str> = asana_tasks
+            .iter()
+            .map(|task| task.memberships[0].section.gid.as_str())
+            .collect();
+        assert_eq!(
+            section_gids,
+            vec!["section:Thesis/Writing", "section:Thesis/Presentation"]
+        );
+
+        // Creation time of tasks is required
+        let csv_export = "Task ID,Created At,Name\n1,2021-03-01,Valid\n2,,Without creation time\n";
+        let err = asana_csv::read_tasks(csv_export.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("task 2: invalid Created At"));
+    }
+
+    fn split_into_pages(asana_tasks: &[asana::Task]) -> Vec<asana::Page> {
+        let chunks: Vec<&[asana::Task]> = asana_tasks.chunks(3).collect();
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| asana::Page {
+                data: chunk.to_vec(),
+                next_page: if index + 1 < chunks.len() {
+                    Some(asana::NextPage {
+                        offset: format!("offset{}", index + 1),
+                        path: None,
+                        uri: None,
+                    })
+                } else {
+                    None
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_paginated_input() {
+        let asana_tasks = read_test_input().data;
+        let mut pages = split_into_pages(&asana_tasks);
+        assert_eq!(pages.len(), 3);
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        // Concatenated stream of pages, with the last task repeated
+        pages[2].data.push(asana_tasks[0].clone());
+        let stream_path = temp_dir.path().join("stream.json");
+        {
+            let mut stream_file = File::create(&stream_path).unwrap();
+            for page in &pages {
+                serde_json::to_writer(&mut stream_file, page).unwrap();
+                writeln!(stream_file).unwrap();
+            }
+        }
+        let mut report = InputReport::default();
+        let stitched = read_asana_tasks(&stream_path, InputFormat::Json, &mut report).unwrap();
+        assert_eq!(stitched.len(), asana_tasks.len());
+        assert_eq!(report.duplicate_tasks, 1);
+        assert!(report.page_chain_warnings.is_empty());
+
+        // Array of pages, with the last page missing
+        let array_path = temp_dir.path().join("array.json");
+        serde_json::to_writer(File::create(&array_path).unwrap(), &pages[..2]).unwrap();
+        let mut report = InputReport::default();
+        let stitched = read_asana_tasks(&array_path, InputFormat::Json, &mut report).unwrap();
+        assert_eq!(stitched.len(), 6);
+        assert_eq!(report.page_chain_warnings.len(), 1);
+
+        // Directory of page files
+        let pages_dir = temp_dir.path().join("pages");
+        std::fs::create_dir(&pages_dir).unwrap();
+        for (index, page) in pages.iter().enumerate() {
+            let page_path = pages_dir.join(format!("page_{}.json", (index + 1) * 5));
+            serde_json::to_writer(File::create(page_path).unwrap(), page).unwrap();
+        }
+        let mut report = InputReport::default();
+        let stitched = read_asana_tasks(&pages_dir, InputFormat::Json, &mut report).unwrap();
+        let gids: Vec<&str> = stitched.iter().map(|task| task.gid.as_str()).collect();
+        let expected_gids: Vec<&str> = asana_tasks.iter().map(|task| task.gid.as_str()).collect();
+        assert_eq!(gids, expected_gids);
+        assert!(report.page_chain_warnings.is_empty());
+    }
+
+    #[test]
+    fn test_streaming_conversion() {
+        let mut asana_tasks = read_test_input().data;
+        // Dependency on a task that is read after the dependent one
+        let last_gid = asana_tasks.last().unwrap().gid.clone();
+        asana_tasks[0].dependencies = Some(vec![task_reference(&last_gid)]);
+        // Task exported top-level before its nested copy
+        let nested_task = asana_tasks[1].clone();
+        asana_tasks[2].subtasks = Some(vec![nested_task]);
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stream_path = temp_dir.path().join("stream.json");
+        {
+            let mut stream_file = File::create(&stream_path).unwrap();
+            for page in split_into_pages(&asana_tasks) {
+                serde_json::to_writer(&mut stream_file, &page).unwrap();
+            }
+        }
+        let options = ConvertOptions::default();
+
+        let mut converter = Converter::new(&options, HashMap::new());
+        let mut input_report = InputReport::default();
+        for_each_asana_task(&stream_path, &mut input_report, |task| {
+            converter.add_task_tree(&task)
+        })
+        .unwrap();
+        assert!(input_report.page_chain_warnings.is_empty());
+        let mut report = ConversionReport::default();
+        converter.prepare(&mut report).unwrap();
+        let mut task_writer = TaskWriter::new(Vec::new());
+        for_each_asana_task(&stream_path, &mut InputReport::default(), |task| {
+            let mut output_tasks = Vec::new();
+            converter
+                .convert_task_tree(task, &mut output_tasks, &mut report)
+                .unwrap();
+            task_writer.write_tasks(&output_tasks).unwrap();
+        })
+        .unwrap();
+        let mut output_tasks = Vec::new();
+        converter.finish(&mut output_tasks);
+        task_writer.write_tasks(&output_tasks).unwrap();
+        let output = task_writer.finish().unwrap();
+        let streamed_tasks: Vec<taskwarrior::Task> = serde_json::from_slice(&output).unwrap();
+
+        assert_eq!(report.duplicate_tasks, 1);
+        let converted_tasks =
+            convert_tasks(asana_tasks, &options, &mut ConversionReport::default()).unwrap();
+        assert_eq!(streamed_tasks.len(), converted_tasks.len());
+        for (streamed_task, converted_task) in streamed_tasks.iter().zip(&converted_tasks) {
+            assert_eq!(streamed_task.description, converted_task.description);
+            assert_eq!(
+                streamed_task.depends.is_some(),
+                converted_task.depends.is_some()
+            );
+        }
+        assert_eq!(
+            streamed_tasks[0].depends,
+            Some(streamed_tasks.last().unwrap().uuid.to_string())
+        );
+    }
+
+    #[test]
+    fn test_compressed_input() {
+        let input_file_path = Path::new("testfiles/input/1194733031423185.json");
+        let input = std::fs::read(input_file_path).unwrap();
+        let tasks_count = read_test_input().data.len();
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let gzip_path = temp_dir.path().join("tasks.json.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&gzip_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(&input).unwrap();
+        encoder.finish().unwrap();
+
+        let zstd_path = temp_dir.path().join("tasks.json.zst");
+        zstd::stream::copy_encode(&input[..], File::create(&zstd_path).unwrap(), 0).unwrap();
+
+        // Archive of two pages, read in natural order of file names
+        let zip_path = temp_dir.path().join("tasks.zip");
+        let mut zip_writer = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        for name in ["page_10.json", "page_2.json"] {
+            zip_writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip_writer.write_all(&input).unwrap();
+        }
+        zip_writer.finish().unwrap();
+
+        for (path, expected_duplicates) in
+            [(&gzip_path, 0), (&zstd_path, 0), (&zip_path, tasks_count)]
+        {
+            assert_eq!(InputFormat::Auto.detect(path), InputFormat::Json);
+            let mut report = InputReport::default();
+            let asana_tasks = read_asana_tasks(path, InputFormat::Auto, &mut report).unwrap();
+            assert_eq!(asana_tasks.len(), tasks_count);
+            assert_eq!(report.duplicate_tasks, expected_duplicates);
+        }
+        assert_eq!(
+            InputFormat::Auto.detect(Path::new("tasks.csv.gz")),
+            InputFormat::Csv
+        );
+    }
+
+    #[test]
+    fn test_stories() {
+        let stories = read_stories(Path::new("testfiles/input/stories.json")).unwrap();
+        assert_eq!(stories.len(), 2);
+
+        for (include_system_stories, expected_annotations) in [
+            (
+                false,
+                vec![
+                    vec![
+                        "Teacher: Deadline is moved to October",
+                        "Artiom Khandamirov: Shift matrix is done, rotation is left",
+                    ],
+                    vec![],
+                ],
+            ),
+            (
+                true,
+                vec![
+                    vec![
+                        "Artiom Khandamirov: assigned to you",
+                        "Teacher: Deadline is moved to October",
+                        "Artiom Khandamirov: Shift matrix is done, rotation is left",
+                    ],
+                    vec!["changed the due date to Oct 1"],
+                ],
+            ),
+        ] {
+            let options = ConvertOptions {
+                stories: stories.clone(),
+                include_system_stories,
+                ..ConvertOptions::default()
+            };
+            let output_taskwarrior_data = convert_tasks(
+                read_test_input().data,
+                &options,
+                &mut ConversionReport::default(),
+            )
+            .unwrap();
+            for (task, expected_annotations) in
+                output_taskwarrior_data.iter().zip(expected_annotations)
+            {
+                let annotations: Vec<&str> = task
+                    .annotations
+                    .iter()
+                    .flatten()
+                    .map(|annotation| annotation.description.as_str())
+                    .collect();
+                assert_eq!(annotations, expected_annotations);
+            }
+        }
+    }
+
+    #[test]
+    fn test_attachments() {
+        let attachments: HashMap<String, Vec<asana::Attachment>> =
+            read_sidecar_resources(Path::new("testfiles/input/attachments.json")).unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let attachments_dir = temp_dir.path().join("attachments");
+        // Two attachments of the same task have the same name
+        for (gid, content) in [
+            ("1194733031600001", "%PDF-1"),
+            ("1194733031600003", "%PDF-3"),
+        ] {
+            std::fs::create_dir_all(attachments_dir.join(gid)).unwrap();
+            std::fs::write(attachments_dir.join(gid).join("report 1.pdf"), content).unwrap();
+        }
+        let copy_dir = temp_dir.path().join("copies");
+
+        let options = ConvertOptions {
+            attachments: find_local_attachments(attachments, &attachments_dir),
+            attachments_copy_directory: Some(copy_dir.clone()),
+            ..ConvertOptions::default()
+        };
+        let mut report = ConversionReport::default();
+        let mut uuids = HashMap::new();
+        let output_taskwarrior_data =
+            convert_tasks_with_uuids(read_test_input().data, &mut uuids, &options, &mut report)
+                .unwrap();
+        assert_eq!(report.missing_attachment_files, 1);
+        let copied = copy_attachments(&options.attachments, &uuids, &copy_dir).unwrap();
+        assert_eq!(copied, 2);
+
+        let uuid = uuids["1194733031423196"];
+        for (gid, content) in [
+            ("1194733031600001", "%PDF-1"),
+            ("1194733031600003", "%PDF-3"),
+        ] {
+            let copy_path = copy_dir
+                .join(uuid.to_string())
+                .join(format!("{}-report 1.pdf", gid));
+            assert_eq!(std::fs::read_to_string(&copy_path).unwrap(), content);
+        }
+        let annotations: Vec<&str> = output_taskwarrior_data[0]
+            .annotations
+            .iter()
+            .flatten()
+            .map(|annotation| annotation.description.as_str())
+            .collect();
+        assert_eq!(annotations.len(), 3);
+        assert!(annotations[0].starts_with("report 1.pdf file:///"));
+        assert!(annotations[0].ends_with("/1194733031600001-report%201.pdf"));
+        assert!(annotations[2].ends_with("/1194733031600003-report%201.pdf"));
+        assert_eq!(
+            annotations[1],
+            "lost.png https://app.asana.com/app/asana/-/get_asset?asset_id=1194733031600002"
+        );
+    }
+
+    #[test]
+    fn test_organization_export_input() {
+        let input_file_path = Path::new("testfiles/input/organization_export.json");
+        let mut report = InputReport::default();
+        let asana_tasks =
+            read_asana_tasks(input_file_path, InputFormat::Organization, &mut report).unwrap();
+        // Unknown follower
+        assert_eq!(report.unresolved_references, 1);
+        assert_eq!(asana_tasks.len(), 2);
+
+        let task = &asana_tasks[0];
+        assert_eq!(task.name, "Add user table");
+        assert_eq!(task.assignee.as_ref().unwrap().name, "Alice");
+        assert_eq!(task.tags[0].name, "urgent");
+        assert_eq!(task.workspace.name, "Example Inc.");
+        assert_eq!(task.memberships.len(), 1);
+        assert_eq!(task.memberships[0].project.name, "Backend");
+        assert_eq!(task.memberships[0].section.name, "To do");
+        let subtasks = task.subtasks.as_ref().unwrap();
+        assert_eq!(subtasks.len(), 1);
+        assert_eq!(subtasks[0].assignee.as_ref().unwrap().name, "Bob");
+
+        let task = &asana_tasks[1];
+        let memberships: Vec<(&str, &str)> = task
+            .memberships
+            .iter()
+            .map(|membership| {
+                (
+                    membership.project.name.as_str(),
+                    membership.section.name.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            memberships,
+            vec![("Backend", "Done"), ("Frontend", "Backlog")]
+        );
+        assert_eq!(task.projects.len(), 2);
+
+        let output_taskwarrior_data = convert_tasks(
+            asana_tasks,
+            &ConvertOptions::default(),
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        assert_eq!(output_taskwarrior_data.len(), 3);
+        assert_eq!(
+            output_taskwarrior_data[1].project.as_deref(),
+            Some("Backend")
+        );
+        assert_eq!(
+            output_taskwarrior_data[2].depends,
+            Some(output_taskwarrior_data[0].uuid.to_string())
+        );
+
+        // Project missing from export is counted once, though it is in memberships and projects
+        let temp_dir = tempfile::tempdir().unwrap();
+        let input_file_path = temp_dir.path().join("organization_export.json");
+        std::fs::write(
+            &input_file_path,
+            r#"{"tasks": [{
+                "gid": "8001",
+                "name": "Add user table",
+                "created_at": "2021-03-01T10:00:00.000Z",
+                "memberships": [{"project": "6999", "section": "7999"}]
+            }]}"#,
+        )
+        .unwrap();
+        let mut report = InputReport::default();
+        let asana_tasks =
+            read_asana_tasks(&input_file_path, InputFormat::Organization, &mut report).unwrap();
+        assert_eq!(report.unresolved_references, 2);
+        assert_eq!(asana_tasks[0].projects[0].gid, "6999");
+    }
+
+    /// Move subtasks to top level after their parents, keeping `parent` references.
+    fn flatten_subtasks(asana_tasks: Vec<asana::Task>, flat_tasks: &mut Vec<asana::Task>) {
+        for mut task in asana_tasks {
+            let subtasks = task.subtasks.take();
+            flat_tasks.push(task);
+            if let Some(subtasks) = subtasks {
+                flatten_subtasks(subtasks, flat_tasks);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rebuild_hierarchy() {
+        let mut asana_tasks = read_test_input().data;
+        let mut parent = asana_tasks.pop().unwrap();
+        let mut subtask = asana_tasks.remove(0);
+        let mut subsubtasks: Vec<asana::Task> = asana_tasks.drain(0..2).collect();
+        for subsubtask in &mut subsubtasks {
+            subsubtask.parent = Some(task_reference(&subtask.gid));
+        }
+        subsubtasks[0].due_on = NaiveDate::from_ymd_opt(2021, 3, 1);
+        subsubtasks[0].tags = vec![asana::TagReference {
+            name: "lab".to_string(),
+            ..asana::TagReference::default()
+        }];
+        subtask.subtasks = Some(subsubtasks);
+        subtask.parent = Some(task_reference(&parent.gid));
+        parent.subtasks = Some(vec![subtask]);
+        asana_tasks.insert(0, parent);
+
+        let options = ConvertOptions::default();
+        let expected_output = convert_tasks(
+            asana_tasks.clone(),
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+
+        // Parent comes after its subtasks, and one of subtasks is exported twice, trimmed when
+        // nested and full at top level
+        let mut flat_tasks = Vec::new();
+        flatten_subtasks(asana_tasks.clone(), &mut flat_tasks);
+        flat_tasks.swap(0, 1);
+        let mut nested_subtask = flat_tasks[0].clone();
+        let mut trimmed_subsubtask = flat_tasks[2].clone();
+        trimmed_subsubtask.due_on = None;
+        trimmed_subsubtask.tags.clear();
+        nested_subtask.subtasks = Some(vec![trimmed_subsubtask]);
+        flat_tasks[0] = nested_subtask;
+        // Subtask of task missing from export
+        let mut orphan_task = asana_tasks[1].clone();
+        orphan_task.gid = "1".to_string();
+        orphan_task.parent = Some(task_reference("2"));
+        flat_tasks.push(orphan_task);
+
+        let mut report = ConversionReport::default();
+        let output_taskwarrior_data = convert_tasks(flat_tasks, &options, &mut report).unwrap();
+        assert_eq!(report.duplicate_tasks, 1);
+        assert_eq!(
+            report.missing_parents,
+            vec![("1".to_string(), "2".to_string())]
+        );
+        assert_eq!(output_taskwarrior_data.len(), expected_output.len() + 1);
+        for (task, expected_task) in output_taskwarrior_data.iter().zip(&expected_output) {
+            assert_eq!(task.description, expected_task.description);
+            assert_eq!(task.project, expected_task.project);
+            assert_eq!(task.priority, expected_task.priority);
+            assert_eq!(task.end, expected_task.end);
+            assert_eq!(task.due, expected_task.due);
+            assert_eq!(task.tags, expected_task.tags);
+            assert_eq!(task.parent.is_some(), expected_task.parent.is_some());
+        }
+    }
+
+    #[test]
+    fn test_assignee_mapping() {
+        let assignee_mapping: AssigneeMapping = serde_json::from_str(
+            r#"{
+                "status_mapping": {
+                    "today": {"scheduled": "today"},
+                    "upcoming": {"scheduled": "eow", "tags": ["upcoming"]},
+                    "later": {"wait": "+2w"}
+                },
+                "section_target": "project"
+            }"#,
+        )
+        .unwrap();
+        // Wednesday
+        let now = Utc.with_ymd_and_hms(2021, 3, 3, 15, 0, 0).unwrap();
+
+        let mut asana_tasks: Vec<asana::Task> =
+            read_test_input().data.into_iter().take(4).collect();
+        for (task, status) in asana_tasks
+            .iter_mut()
+            .zip(["today", "upcoming", "later", "inbox"])
+        {
+            task.completed = false;
+            task.completed_at = None;
+            task.assignee_status = status.to_string();
+            task.memberships.clear();
+            task.assignee_section = Some(asana::SectionReference {
+                gid: status.to_string(),
+                resource_type: "section".to_string(),
+                name: format!("{} section", status),
+            });
+        }
+
+        let options = ConvertOptions {
+            assignee_mapping,
+            reference_time: Some(now),
+            ..ConvertOptions::default()
+        };
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks, &options, &mut ConversionReport::default()).unwrap();
+        assert_eq!(
+            output_taskwarrior_data[0].scheduled,
+            Some(Utc.with_ymd_and_hms(2021, 3, 3, 0, 0, 0).unwrap())
+        );
+        assert_eq!(
+            output_taskwarrior_data[1].scheduled,
+            Some(
+                Utc.with_ymd_and_hms(2021, 3, 7, 23, 59, 59).unwrap()
+                    + chrono::Duration::milliseconds(999)
+            )
+        );
+        assert!(output_taskwarrior_data[1]
+            .tags
+            .as_ref()
+            .unwrap()
+            .contains(&"upcoming".to_string()));
+        assert_eq!(
+            output_taskwarrior_data[2].status,
+            taskwarrior::Status::Waiting
+        );
+        assert_eq!(
+            output_taskwarrior_data[2].wait,
+            Some(Utc.with_ymd_and_hms(2021, 3, 17, 0, 0, 0).unwrap())
+        );
+        assert_eq!(
+            output_taskwarrior_data[3].status,
+            taskwarrior::Status::Pending
+        );
+        assert_eq!(output_taskwarrior_data[3].scheduled, None);
+        assert_eq!(
+            output_taskwarrior_data[3].project.as_deref(),
+            Some("inbox section")
+        );
+
+        for overflowing in ["+99999999999w", "+9223372036854775807d"] {
+            assert!(serde_json::from_str::<AssigneeMapping>(&format!(
+                r#"{{"status_mapping": {{"later": {{"wait": "{}"}}}}}}"#,
+                overflowing
+            ))
+            .is_err());
+        }
+        let far_future: AssigneeMapping =
+            serde_json::from_str(r#"{"status_mapping": {"later": {"wait": "+100000000000d"}}}"#)
+                .unwrap();
+        assert_eq!(
+            far_future.status_mapping["later"]
+                .wait
+                .as_ref()
+                .unwrap()
+                .resolve(now),
+            DateTime::<Utc>::MAX_UTC
+        );
+    }
+
+    #[test]
+    fn test_section_positions() {
+        let sections: HashMap<String, Vec<asana::Section>> =
+            read_sidecar_resources(Path::new("testfiles/input/sections.json")).unwrap();
+        let section_positions = get_section_positions(&sections);
+        assert_eq!(section_positions["1194733031423188"], 1);
+
+        let section_priority_mapping: SectionPriorityMapping = serde_json::from_str(
+            r#"{"default_mapping": "L", "position_mapping": [["H", 1], ["M", 1]]}"#,
+        )
+        .unwrap();
+        let options = ConvertOptions {
+            section_priority_mapping,
+            section_positions,
+            ..ConvertOptions::default()
+        };
+        let output_taskwarrior_data = convert_tasks(
+            read_test_input().data,
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        assert_eq!(output_taskwarrior_data[0].priority, Some(Priority::Medium));
+        assert_eq!(output_taskwarrior_data[7].priority, Some(Priority::High));
+
+        // Gid mapping is used even if section was renamed
+        let mut options = options;
+        options.section_priority_mapping = serde_json::from_str(
+            r#"{"default_mapping": "L", "gid_mapping": {"1197505710233958": "M"}}"#,
+        )
+        .unwrap();
+        let mut asana_tasks = read_test_input().data;
+        asana_tasks[7].memberships[0].section.name = "Renamed".to_string();
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks, &options, &mut ConversionReport::default()).unwrap();
+        assert_eq!(output_taskwarrior_data[0].priority, Some(Priority::Low));
+        assert_eq!(output_taskwarrior_data[7].priority, Some(Priority::Medium));
+    }
+
+    #[test]
+    fn test_projects() {
+        let projects = read_projects(Path::new("testfiles/input/projects.json")).unwrap();
+        assert!(projects["1194733031423190"].archived);
+        let project_due = Utc.with_ymd_and_hms(2021, 12, 31, 23, 59, 59).unwrap()
+            + chrono::Duration::milliseconds(999);
+
+        let mut options = ConvertOptions {
+            projects,
+            project_due_dates: ProjectDueDateTarget::Due,
+            ..ConvertOptions::default()
+        };
+        let output_taskwarrior_data = convert_tasks(
+            read_test_input().data,
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        assert_eq!(output_taskwarrior_data[0].due, Some(project_due));
+        assert_ne!(output_taskwarrior_data[3].due, Some(project_due));
+        assert_eq!(output_taskwarrior_data[0].until, None);
+
+        // Subtasks without memberships get due date of project of their parent
+        let mut asana_tasks = read_test_input().data;
+        let mut subtask = asana_tasks[1].clone();
+        subtask.memberships.clear();
+        asana_tasks[0].subtasks = Some(vec![subtask]);
+        asana_tasks.truncate(1);
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks, &options, &mut ConversionReport::default()).unwrap();
+        assert_eq!(output_taskwarrior_data[1].due, Some(project_due));
+
+        options.project_due_dates = ProjectDueDateTarget::Until;
+        let output_taskwarrior_data = convert_tasks(
+            read_test_input().data,
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        assert_eq!(output_taskwarrior_data[0].due, None);
+        assert_eq!(output_taskwarrior_data[0].until, Some(project_due));
+        assert_eq!(output_taskwarrior_data[3].until, None);
+        assert!(output_taskwarrior_data[0].udas.is_empty());
+
+        options.project_owners = true;
+        let output_taskwarrior_data = convert_tasks(
+            read_test_input().data,
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        for task in &output_taskwarrior_data {
+            assert_eq!(
+                task.udas.get(PROJECT_OWNER_UDA),
+                Some(&serde_json::Value::from("Artiom Khandamirov"))
+            );
+        }
+
+        // Last task is moved to archived project
+        let mut asana_tasks = read_test_input().data;
+        asana_tasks[7].memberships[0].project.gid = "1194733031423190".to_string();
+        let modified_at = asana_tasks[7].modified_at;
+
+        options.archived_projects = ArchivedProjectPolicy::Skip;
+        let mut report = ConversionReport::default();
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks.clone(), &options, &mut report).unwrap();
+        assert_eq!(output_taskwarrior_data.len(), 7);
+        assert_eq!(report.archived_project_tasks, 1);
+
+        options.archived_projects = ArchivedProjectPolicy::Complete;
+        let output_taskwarrior_data = convert_tasks(
+            asana_tasks.clone(),
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        assert_eq!(output_taskwarrior_data.len(), 8);
+        assert_eq!(
+            output_taskwarrior_data[7].status,
+            taskwarrior::Status::Completed
+        );
+        assert_eq!(output_taskwarrior_data[7].end, Some(modified_at));
+
+        // Modification time missing from export
+        asana_tasks[7].modified_at = DateTime::default();
+        let created_at = asana_tasks[7].created_at;
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks, &options, &mut ConversionReport::default()).unwrap();
+        assert_eq!(output_taskwarrior_data[7].end, Some(created_at));
+    }
+
+    #[test]
+    fn test_project_hierarchy() {
+        let projects = read_projects(Path::new("testfiles/input/projects.json")).unwrap();
+        let mut teams: Vec<asana::Team> =
+            read_resources(Path::new("testfiles/input/teams.json")).unwrap();
+        let portfolios: Vec<asana::Portfolio> =
+            read_resources(Path::new("testfiles/input/portfolios.json")).unwrap();
+
+        // Team is found by `team` of project, first portfolio of project is used
+        let options = ConvertOptions {
+            project_hierarchy: ProjectHierarchy::new(&teams, &portfolios, &projects),
+            append_sections_to_project: true,
+            ..ConvertOptions::default()
+        };
+        let output_taskwarrior_data = convert_tasks(
+            read_test_input().data,
+            &options,
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            output_taskwarrior_data[7].project.as_deref(),
+            Some("MAI.Semester5.МАИ: компьютерная графика.Теория")
+        );
+
+        // Projects listed by team take precedence over `team` of project
+        let project = asana::Reference {
+            gid: "1194733031423185".to_string(),
+            resource_type: "project".to_string(),
+            name: "МАИ: компьютерная графика".to_string(),
+        };
+        teams.push(asana::Team {
+            gid: "1194733031423181".to_string(),
+            resource_type: "team".to_string(),
+            name: "Graphics".to_string(),
+            projects: vec![project.clone()],
+        });
+        let hierarchy = ProjectHierarchy::new(&teams, &[], &projects);
+        assert_eq!(
+            hierarchy.get_project_name(&project),
+            "Graphics.МАИ: компьютерная графика"
+        );
+
+        // Dots in team, portfolio and section names don't add levels
+        let hierarchy = ProjectHierarchy {
+            teams: HashMap::from([(project.gid.clone(), "Dept. of Graphics".to_string())]),
+            portfolios: HashMap::from([(project.gid.clone(), "2021.1".to_string())]),
+        };
+        let section = asana::SectionReference {
+            name: "Lab. 1".to_string(),
+            ..asana::SectionReference::default()
+        };
+        assert_eq!(
+            hierarchy.get_section_project_name(&project, &section),
+            "Dept_ of Graphics.2021_1.МАИ: компьютерная графика.Lab_ 1"
+        );
+        assert_eq!(
+            ProjectHierarchy::default().get_section_project_name(&project, &section),
+            "МАИ: компьютерная графика. Lab_ 1"
+        );
+        assert_eq!(
+            ProjectHierarchy::default().get_project_name(&asana::Reference {
+                name: "Project".to_string(),
+                ..asana::Reference::default()
+            }),
+            "Project"
+        );
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let tasks = convert_tasks(
+            read_test_input().data,
+            &ConvertOptions::default(),
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+
+        let mut task_writer = TaskWriter::with_format(Vec::new(), OutputFormat::Jsonl, false);
+        task_writer.write_tasks(&tasks).unwrap();
+        let output = String::from_utf8(task_writer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), tasks.len());
+        for (line, task) in lines.iter().zip(&tasks) {
+            let line_task: taskwarrior::Task = serde_json::from_str(line).unwrap();
+            assert_tasks_equal(&line_task, task);
+        }
+
+        let mut task_writer = TaskWriter::with_format(Vec::new(), OutputFormat::Json, true);
+        task_writer.write_tasks(&tasks).unwrap();
+        let output = String::from_utf8(task_writer.finish().unwrap()).unwrap();
+        assert!(output.starts_with("[\n  {\n    \"status\""));
+        let pretty_tasks: Vec<taskwarrior::Task> = serde_json::from_str(&output).unwrap();
+        assert_eq!(pretty_tasks.len(), tasks.len());
+        assert_tasks_equal(&pretty_tasks[0], &tasks[0]);
+
+        let task_writer = TaskWriter::with_format(Vec::new(), OutputFormat::Json, true);
+        assert_eq!(task_writer.finish().unwrap(), b"[]\n");
+    }
+
+    #[test]
+    fn test_taskchampion_replica() {
+        let mut tasks = convert_tasks(
+            read_test_input().data,
+            &ConvertOptions::default(),
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        tasks[7].tags = Some(vec!["asana".to_string()]);
+        tasks[7]
+            .udas
+            .insert(PROJECT_OWNER_UDA.to_string(), "Alice".into());
+        let modified = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap();
+        tasks[0].modified = Some(modified);
+        let replica_directory = tempfile::tempdir().unwrap();
+
+        let report = write_to_replica(replica_directory.path(), &tasks).unwrap();
+        assert_eq!(report.created_tasks, 8);
+        let properties = get_task_properties(&tasks[7]).unwrap();
+        assert_eq!(properties["status"], "pending");
+        assert_eq!(properties["tag_asana"], "");
+        assert_eq!(properties["entry"], tasks[7].entry.timestamp().to_string());
+
+        // Tasks with the same UUID are updated, properties of other tools are kept
+        let mut replica = taskchampion::Replica::new(
+            taskchampion::StorageConfig::OnDisk {
+                taskdb_dir: replica_directory.path().to_path_buf(),
+                create_if_missing: false,
+                access_mode: taskchampion::storage::AccessMode::ReadWrite,
+            }
+            .into_storage()
+            .unwrap(),
+        );
+        let mut operations = taskchampion::Operations::new();
+        let mut task_data = replica.get_task_data(tasks[7].uuid.uuid).unwrap().unwrap();
+        task_data.update("other_uda", Some("value".to_string()), &mut operations);
+        replica.commit_operations(operations).unwrap();
+        drop(replica);
+
+        // Task converted again gets new modification time, but nothing else changed
+        tasks[0].modified = None;
+        tasks[7].description = "Renamed".to_string();
+        tasks[7].tags = None;
+        tasks[7].udas.clear();
+        let replica_file = replica_directory.path().join("taskchampion.sqlite3");
+        let report = write_to_replica(&replica_file, &tasks).unwrap();
+        assert_eq!(report.created_tasks, 0);
+        assert_eq!(report.updated_tasks, 1);
+        assert_eq!(report.unchanged_tasks, 7);
+
+        let mut replica = taskchampion::Replica::new(
+            taskchampion::StorageConfig::OnDisk {
+                taskdb_dir: replica_directory.path().to_path_buf(),
+                create_if_missing: false,
+                access_mode: taskchampion::storage::AccessMode::ReadOnly,
+            }
+            .into_storage()
+            .unwrap(),
+        );
+        assert_eq!(replica.all_task_uuids().unwrap().len(), 8);
+        let task_data = replica.get_task_data(tasks[7].uuid.uuid).unwrap().unwrap();
+        assert_eq!(task_data.get("description"), Some("Renamed"));
+        assert_eq!(task_data.get("tag_asana"), None);
+        assert_eq!(task_data.get(PROJECT_OWNER_UDA), None);
+        assert_eq!(task_data.get("other_uda"), Some("value"));
+        let unchanged_task_data = replica.get_task_data(tasks[0].uuid.uuid).unwrap().unwrap();
+        assert_eq!(
+            unchanged_task_data.get("modified"),
+            Some(modified.timestamp().to_string().as_str())
+        );
+        assert_eq!(replica.working_set().unwrap().largest_index(), 1);
+    }
+
+    #[test]
+    fn test_taskwarrior_data_files() {
+        let mut tasks = convert_tasks(
+            read_test_input().data,
+            &ConvertOptions::default(),
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        tasks[7].description = "Read \"Book\" [part 1]\nand 2".to_string();
+        tasks[7].tags = Some(vec!["asana".to_string(), "read".to_string()]);
+        tasks[7].depends = Some(
+            [tasks[0].uuid.clone(), tasks[1].uuid.clone()]
+                .iter()
+                .map(|uuid| uuid.to_string())
+                .collect::<Vec<String>>()
+                .join(","),
+        );
+        tasks[7].annotations = Some(vec![taskwarrior::Annotation {
+            entry: Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+            description: "Note".to_string(),
+        }]);
+
+        let line = get_f4_line(&tasks[7]).unwrap();
+        assert!(line.starts_with("[annotation_1609459200:\"Note\" depends:\""));
+        assert!(line.contains(r#"description:"Read \"Book\" &open;part 1&close;\nand 2" "#));
+        assert!(line.contains(" tags:\"asana,read\" "));
+        assert!(line.ends_with(&format!(" uuid:\"{}\"]", tasks[7].uuid.uuid)));
+        let depends_start = line.find("depends:\"").unwrap() + "depends:\"".len();
+        let depends = &line[depends_start..depends_start + 73];
+        assert_eq!(depends, depends.to_lowercase());
+
+        let data_directory = tempfile::tempdir().unwrap();
+        let pending_path = data_directory.path().join("pending.data");
+        let foreign_line = "[description:\"Other\" entry:\"1600000000\" status:\"pending\" uuid:\"00000000-0000-4000-8000-000000000000\"]";
+        std::fs::write(&pending_path, format!("{}\n", foreign_line)).unwrap();
+        let report = write_data_files(data_directory.path(), &tasks).unwrap();
+        assert_eq!(report.added_tasks, 8);
+        let pending_data = std::fs::read_to_string(&pending_path).unwrap();
+        assert_eq!(pending_data.lines().count(), 2);
+        assert_eq!(pending_data.lines().next(), Some(foreign_line));
+
+        // Replaced tasks keep their lines, completed tasks move to completed data
+        tasks[7].status = taskwarrior::Status::Completed;
+        tasks[7].end = Some(Utc::now());
+        tasks[0].description = "Renamed".to_string();
+        // Files are rewritten in place, so that file opened before sees new contents
+        let mut opened_pending_file = File::open(&pending_path).unwrap();
+        let report = write_data_files(data_directory.path(), &tasks).unwrap();
+        assert_eq!(report.added_tasks, 0);
+        assert_eq!(report.replaced_tasks, 8);
+        let mut pending_data = String::new();
+        opened_pending_file
+            .read_to_string(&mut pending_data)
+            .unwrap();
+        assert_eq!(pending_data, format!("{}\n", foreign_line));
+        let completed_data =
+            std::fs::read_to_string(data_directory.path().join("completed.data")).unwrap();
+        let completed_lines: Vec<&str> = completed_data.lines().collect();
+        assert_eq!(completed_lines.len(), 8);
+        assert!(completed_lines[0].contains("description:\"Renamed\""));
+        assert!(completed_lines[7].contains("status:\"completed\""));
+
+        // Locked data files are not touched
+        let lock = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&pending_path)
+            .unwrap();
+        assert!(try_lock(&lock).unwrap());
+        assert!(matches!(
+            write_data_files(data_directory.path(), &tasks),
+            Err(DataFilesError::Locked(_))
+        ));
+    }
+
+    /// Get converted test tasks, last one waiting with annotation and dependencies on first two
+    /// tasks, and second one being its subtask.
+    fn get_versioned_test_tasks() -> Vec<taskwarrior::Task> {
+        let mut tasks = convert_tasks(
+            read_test_input().data,
+            &ConvertOptions::default(),
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        tasks[7].status = taskwarrior::Status::Waiting;
+        tasks[7].wait = Some(Utc.with_ymd_and_hms(2030, 1, 2, 3, 4, 5).unwrap());
+        tasks[7].depends = Some(format!("{},{}", tasks[1].uuid, tasks[0].uuid));
+        tasks[7].annotations = Some(vec![taskwarrior::Annotation {
+            entry: Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+            description: "Note".to_string(),
+        }]);
+        tasks[1].parent = Some(tasks[7].uuid.clone());
+        tasks
+    }
+
+    /// Check fields that are the same in all Taskwarrior versions.
+    fn assert_versioned_task(
+        tasks: &[taskwarrior::Task],
+        version: TaskwarriorVersion,
+    ) -> serde_json::Value {
+        let waiting_task = get_versioned_task(&tasks[7], version).unwrap();
+        let uuid = tasks[7].uuid.uuid.to_string();
+        assert_eq!(waiting_task["uuid"], uuid.as_str());
+        assert_eq!(waiting_task["wait"], "20300102T030405Z");
+        assert_eq!(waiting_task["annotations"][0]["entry"], "20210101T000000Z");
+        assert!(waiting_task.get("tags").is_none());
+        let subtask = get_versioned_task(&tasks[1], version).unwrap();
+        assert_eq!(subtask["parent"], uuid.as_str());
+        waiting_task
+    }
+
+    #[test]
+    fn test_taskwarrior_2_5_profile() {
+        let tasks = get_versioned_test_tasks();
+        let waiting_task = assert_versioned_task(&tasks, TaskwarriorVersion::V2_5);
+        assert_eq!(waiting_task["status"], "waiting");
+        let mut dependencies = [
+            tasks[0].uuid.uuid.to_string(),
+            tasks[1].uuid.uuid.to_string(),
+        ];
+        dependencies.sort();
+        assert_eq!(waiting_task["depends"], dependencies.join(",").as_str());
+    }
+
+    #[test]
+    fn test_taskwarrior_2_6_profile() {
+        let tasks = get_versioned_test_tasks();
+        let waiting_task = assert_versioned_task(&tasks, TaskwarriorVersion::V2_6);
+        assert_eq!(waiting_task["status"], "pending");
+        let depends = waiting_task["depends"].as_array().unwrap();
+        assert_eq!(depends.len(), 2);
+        assert!(depends.contains(&serde_json::Value::from(tasks[0].uuid.uuid.to_string())));
+    }
+
+    #[test]
+    fn test_taskwarrior_3_profile() {
+        let tasks = get_versioned_test_tasks();
+        let waiting_task = assert_versioned_task(&tasks, TaskwarriorVersion::V3);
+        assert_eq!(waiting_task["status"], "pending");
+        assert!(waiting_task["depends"].is_array());
+
+        // Profile is applied by writer, default output is kept
+        let mut task_writer = TaskWriter::with_format(Vec::new(), OutputFormat::Jsonl, false)
+            .with_version(Some(TaskwarriorVersion::V3));
+        task_writer.write_tasks(&tasks).unwrap();
+        let output = String::from_utf8(task_writer.finish().unwrap()).unwrap();
+        let last_line: serde_json::Value =
+            serde_json::from_str(output.lines().last().unwrap()).unwrap();
+        assert_eq!(last_line, waiting_task);
+        let default_task = serde_json::to_value(&tasks[7]).unwrap();
+        assert_eq!(default_task["status"], "waiting");
+        assert_eq!(default_task["uuid"], tasks[7].uuid.to_string().as_str());
+    }
+
+    #[test]
+    fn test_todotxt_output() {
+        let mut tasks = get_versioned_test_tasks();
+        tasks[7].priority = Some(Priority::High);
+        tasks[7].tags = Some(vec!["read later".to_string()]);
+        tasks[0].priority = Some(Priority::Low);
+
+        let mut task_writer = TaskWriter::with_format(Vec::new(), OutputFormat::TodoTxt, false);
+        task_writer.write_tasks(&tasks).unwrap();
+        let output = String::from_utf8(task_writer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), tasks.len());
+
+        assert_eq!(
+            lines[7],
+            format!(
+                "(A) 2020-10-06 Прочитать книжку Сиденко +МАИ:_компьютерная_графика @read_later \
+                 due:2021-08-31 t:2030-01-02 uuid:{} dep:{} gid:1197505710233961",
+                tasks[7].uuid.uuid,
+                tasks[7].depends.as_ref().unwrap().to_lowercase()
+            )
+        );
+        assert!(lines[0].starts_with("x 2020-10-26 2020-09-20 Лабораторная работа №1: "));
+        assert!(lines[0].contains(" pri:C uuid:"));
+        assert!(lines[1].contains(&format!(" parent:{} ", tasks[7].uuid.uuid)));
+
+        // Description words that look like metadata are escaped
+        tasks[2].description =
+            "(A) 2021-01-01 Call +mom @home due:tomorrow, see https://asana.com".to_string();
+        assert!(get_todotxt_line(&tasks[2]).contains(
+            " \\(A) \\2021-01-01 Call \\+mom \\@home due::tomorrow, see https://asana.com "
+        ));
+    }
+
+    #[test]
+    fn test_asana_order() {
+        let mut asana_tasks = read_test_input().data;
+        let subtask = asana_tasks.remove(1);
+        asana_tasks[0].subtasks = Some(vec![subtask]);
+
+        let options = ConvertOptions {
+            asana_order_stride: Some(100),
+            ..ConvertOptions::default()
+        };
+        let output_taskwarrior_data =
+            convert_tasks(asana_tasks, &options, &mut ConversionReport::default()).unwrap();
+        let orders: Vec<f64> = output_taskwarrior_data
+            .iter()
+            .map(|task| task.udas[ASANA_ORDER_UDA].as_f64().unwrap())
+            .collect();
+        // Sections in order of first appearance, subtask between its parent and next task
+        assert_eq!(orders, vec![0.0, 0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 100.0]);
+
+        let mut sorted_tasks = output_taskwarrior_data.clone();
+        sorted_tasks.reverse();
+        sorted_tasks.sort_by(|task1, task2| {
+            let order1 = task1.udas[ASANA_ORDER_UDA].as_f64().unwrap();
+            let order2 = task2.udas[ASANA_ORDER_UDA].as_f64().unwrap();
+            order1.partial_cmp(&order2).unwrap()
+        });
+        let descriptions: Vec<&str> = sorted_tasks
+            .iter()
+            .map(|task| task.description.as_str())
+            .collect();
+        let expected_descriptions: Vec<&str> = output_taskwarrior_data
+            .iter()
+            .map(|task| task.description.as_str())
+            .collect();
+        assert_eq!(descriptions, expected_descriptions);
+        assert!(get_asana_order_taskrc().contains("sort=project+,asana_order+"));
+
+        // Stride must exceed number of tasks in section, orders must stay exact
+        for stride in [5, 1 << 53, u64::MAX].iter() {
+            let options = ConvertOptions {
+                asana_order_stride: Some(*stride),
+                ..ConvertOptions::default()
+            };
+            let result = convert_tasks(
+                read_test_input().data,
+                &options,
+                &mut ConversionReport::default(),
+            );
+            match stride {
+                5 => assert!(matches!(
+                    result,
+                    Err(ConvertError::AsanaOrderStrideExceeded(_, 5))
+                )),
+                _ => assert!(matches!(result, Err(ConvertError::AsanaOrderOverflow(_)))),
+            }
+        }
+    }
+
+    #[test]
+    fn test_trimmed_opt_fields_input() {
+        let input_file_path = Path::new("testfiles/input/trimmed_opt_fields.json");
+        let mut report = InputReport::default();
+        let asana_tasks =
+            read_asana_tasks(input_file_path, InputFormat::Json, &mut report).unwrap();
+
+        assert_eq!(asana_tasks.len(), 2);
+        assert_eq!(report.missing_fields.get("assignee_status"), Some(&2));
+        assert_eq!(report.missing_fields.get("memberships"), Some(&1));
+        assert_eq!(report.missing_fields.get("completed"), None);
+        // Optional fields are not reported
+        assert_eq!(report.missing_fields.get("due_on"), None);
+        assert_eq!(report.missing_fields.get("parent"), None);
+        let custom_fields = asana_tasks[0].custom_fields.as_ref().unwrap();
+        assert_eq!(custom_fields[0].display_value, None);
+
+        let output_taskwarrior_data = convert_tasks(
+            asana_tasks,
+            &ConvertOptions::default(),
+            &mut ConversionReport::default(),
+        )
+        .unwrap();
+        assert_eq!(output_taskwarrior_data.len(), 2);
+        assert_eq!(output_taskwarrior_data[0].project, None);
+        assert_eq!(
+            output_taskwarrior_data[1].status,
+            taskwarrior::Status::Completed
+        );
+    }
+
+    #[test]
+    fn test_custom_fields() {
+        let input_file_path = Path::new("testfiles/input/custom_fields.json");
+        let mut report = InputReport::default();
+        let asana_tasks =
+            read_asana_tasks(input_file_path, InputFormat::Json, &mut report).unwrap();
+
+        let custom_fields = asana_tasks[0].custom_fields.as_ref().unwrap();
+        assert_eq!(custom_fields.len(), 6);
+        assert_eq!(
+            custom_fields[0].custom_label_position,
+            Some(asana::CustomLabelPosition::Suffix)
+        );
+        assert_eq!(custom_fields[0].format, asana::CustomFieldFormat::Currency);
+        assert!(custom_fields[1]
+            .date_value
+            .as_ref()
+            .is_some_and(|date_value| date_value.date.is_some()));
+        assert_eq!(custom_fields[2].people_value.as_ref().unwrap().len(), 1);
+        assert_eq!(
+            custom_fields[3].representation_type,
+            Some(asana::CustomFieldRepresentationType::CustomId)
+        );
+        assert_eq!(custom_fields[3].id_prefix.as_deref(), Some("REL"));
+        assert_eq!(custom_fields[4].format, asana::CustomFieldFormat::Unknown);
+        assert_eq!(
+            custom_fields[4].representation_type,
+            Some(asana::CustomFieldRepresentationType::Unknown)
+        );
+        assert_eq!(
+            custom_fields[4].custom_label_position,
+            Some(asana::CustomLabelPosition::Unknown)
+        );
+    }
 }
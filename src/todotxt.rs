@@ -0,0 +1,112 @@
+//! todo.txt rendering of Taskwarrior tasks, see https://github.com/todotxt/todo.txt
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::taskwarrior;
+
+/// Format date as todo.txt does: `YYYY-MM-DD`.
+fn format_date(date: &DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+/// Make single word of project or context name, replacing whitespace with underscores.
+fn get_word(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join("_")
+}
+
+/// Check if word is read as `key:value` metadata: key and value without colons, value not
+/// starting with `/` (so that URLs stay text).
+fn is_key_value(word: &str) -> bool {
+    match word.split_once(':') {
+        Some((key, value)) => {
+            !key.is_empty() && !value.is_empty() && !value.starts_with(['/', ':'])
+        }
+        None => false,
+    }
+}
+
+/// Check if word is read as priority or date when it starts task text.
+fn is_leading_metadata(word: &str) -> bool {
+    let bytes = word.as_bytes();
+    let is_priority =
+        bytes.len() == 3 && bytes[0] == b'(' && bytes[1].is_ascii_uppercase() && bytes[2] == b')';
+    is_priority || (word.len() == 10 && NaiveDate::parse_from_str(word, "%Y-%m-%d").is_ok())
+}
+
+/// Escape words of description that would be read as metadata: `+project`, `@context` and
+/// leading priority or date get `\` prefix, colon of `key:value` is doubled.
+fn escape_description(description: &str) -> Vec<String> {
+    let mut is_leading = true;
+    description
+        .split_whitespace()
+        .map(|word| {
+            let is_tag = word.len() > 1 && word.starts_with(['+', '@']);
+            let escaped = if is_tag || (is_leading && is_leading_metadata(word)) {
+                format!("\\{}", word)
+            } else if is_key_value(word) {
+                word.replacen(':', "::", 1)
+            } else {
+                word.to_string()
+            };
+            is_leading = is_leading && is_leading_metadata(word);
+            escaped
+        })
+        .collect()
+}
+
+/// Get todo.txt priority letter of Taskwarrior priority.
+fn get_priority_letter(priority: &taskwarrior::Priority) -> char {
+    match priority {
+        taskwarrior::Priority::High => 'A',
+        taskwarrior::Priority::Medium => 'B',
+        taskwarrior::Priority::Low => 'C',
+    }
+}
+
+/// Get todo.txt line of task (without line ending).
+///
+/// Line is `x <end> <entry>` for completed (and deleted) tasks or `(<priority>) <entry>` for other
+/// tasks, then description (escaped, see `escape_description`), `+project`, `@context` of each
+/// tag and key-values: `due:` and `t:` (threshold, from `wait` or `scheduled`) dates, `pri:` of
+/// completed tasks, `uuid:`, `parent:` and `dep:` (comma-joined) UUIDs, `gid:` of Asana task.
+pub fn get_todotxt_line(task: &taskwarrior::Task) -> String {
+    let mut words: Vec<String> = Vec::new();
+    let is_completed = matches!(
+        task.status,
+        taskwarrior::Status::Completed | taskwarrior::Status::Deleted
+    );
+    if is_completed {
+        words.push("x".to_string());
+        words.push(format_date(task.end.as_ref().unwrap_or(&task.entry)));
+    } else if let Some(priority) = &task.priority {
+        words.push(format!("({})", get_priority_letter(priority)));
+    }
+    words.push(format_date(&task.entry));
+    words.extend(escape_description(&task.description));
+
+    if let Some(project) = &task.project {
+        words.push(format!("+{}", get_word(project)));
+    }
+    for tag in task.tags.iter().flatten() {
+        words.push(format!("@{}", get_word(tag)));
+    }
+    if let Some(due) = &task.due {
+        words.push(format!("due:{}", format_date(due)));
+    }
+    if let Some(threshold) = task.wait.as_ref().or(task.scheduled.as_ref()) {
+        words.push(format!("t:{}", format_date(threshold)));
+    }
+    if let (true, Some(priority)) = (is_completed, &task.priority) {
+        words.push(format!("pri:{}", get_priority_letter(priority)));
+    }
+    words.push(format!("uuid:{}", task.uuid.uuid));
+    if let Some(parent) = &task.parent {
+        words.push(format!("parent:{}", parent.uuid));
+    }
+    if let Some(depends) = &task.depends {
+        words.push(format!("dep:{}", depends.to_lowercase()));
+    }
+    if let Some(gid) = &task.asana_gid {
+        words.push(format!("gid:{}", gid));
+    }
+    words.join(" ")
+}